@@ -1,94 +1,175 @@
-use std::collections::HashMap;
+use std::collections::HashSet;
+
+mod render;
 
 fn main() {
-    let (grid, start) = Grid::parse(include_str!("../input.txt"), Tile::NorthSouth);
-    let (a1, this_loop) = grid.loop_containing(start);
-    println!("Q1: {a1}");
-    let a2 = grid.q2(&this_loop);
+    let (grid, start) = Grid::parse(include_str!("../input.txt")).unwrap();
+    let this_loop = grid.loop_containing(start);
+    for (flag, style) in [
+        ("--render", render::Style::Plain),
+        ("--render-ansi", render::Style::Ansi),
+    ] {
+        if std::env::args().any(|arg| arg == flag) {
+            print!("{}", grid.render(start, &this_loop.points, style));
+            return;
+        }
+    }
+    let a1 = this_loop.max_distance();
+    let farthest: Vec<_> = this_loop.farthest().iter().map(|p| p.to_string()).collect();
+    println!(
+        "Q1: {a1} (loop length {}, farthest at {})",
+        this_loop.len(),
+        farthest.join(" and ")
+    );
+    let a2 = enclosed_by(&this_loop.points);
+    debug_assert_eq!(a2, grid.q2(&this_loop.points));
     println!("Q2: {a2}");
 }
 
 struct Grid(Vec<Vec<Tile>>);
 
 impl Grid {
-    /// Returns the start index.
-    fn parse(s: &str, start_is: Tile) -> (Grid, Point) {
+    /// Returns the start index. The start tile is replaced with whichever pipe
+    /// connects to its neighbours.
+    fn parse(s: &str) -> Result<(Grid, Point), StartError> {
         let mut g = Grid(
             s.lines()
                 .map(|line| line.chars().map(Tile::parse).collect())
                 .collect(),
         );
-        let start = find_start(&g);
-        g.0[start.y][start.x] = start_is;
-        (g, start)
+        let start = find_start(&g).ok_or(StartError::Missing)?;
+        g.0[start.y][start.x] = g.infer_start_tile(start)?;
+        Ok((g, start))
+    }
+
+    /// Works out which pipe is under the start, from which neighbouring pipes connect back to it.
+    fn infer_start_tile(&self, start: Point) -> Result<Tile, StartError> {
+        let connected: Vec<_> = Direction::ALL
+            .into_iter()
+            .filter(|dir| {
+                start
+                    .step(*dir)
+                    .and_then(|neighbour| self.at(neighbour))
+                    .is_some_and(|tile| tile.connects(dir.opposite()))
+            })
+            .collect();
+        let candidates: Vec<_> = Tile::PIPES
+            .into_iter()
+            .filter(|tile| tile.connections().iter().all(|dir| connected.contains(dir)))
+            .collect();
+        match candidates.as_slice() {
+            [] => Err(StartError::Impossible),
+            [tile] => Ok(*tile),
+            _ => Err(StartError::Ambiguous(candidates)),
+        }
     }
     fn at(&self, Point { x, y }: Point) -> Option<Tile> {
         let row = self.0.get(y)?;
         row.get(x).copied()
     }
 
-    /// Find the set of points that are in the same loop, the loop which contains `start`.
-    /// Also return the maximum distance from `start` of any point in the loop.
-    fn loop_containing(&self, start: Point) -> (u64, HashMap<Point, Tile>) {
-        let mut dist = 0;
-        let mut frontier = vec![start];
-        // Map each seen node to its distance from the start.
-        let mut seen: HashMap<Point, Tile> = HashMap::new();
-        while !frontier.is_empty() {
-            // Find each neighbour of the frontier, and mark the frontier's distance.
-            let mut next_frontier = Vec::new();
-            for frontier_node in frontier.drain(0..) {
-                seen.insert(frontier_node, self.at(frontier_node).unwrap());
-                for neighbour in self.neighbours_of(frontier_node) {
-                    if !seen.contains_key(&neighbour) {
-                        next_frontier.push(neighbour);
-                    }
-                }
+    /// Walk the loop which contains `start`, beginning at `start`.
+    fn loop_containing(&self, start: Point) -> PipeLoop {
+        let mut points = vec![start];
+        let mut prev = start;
+        let mut curr = start;
+        // Keep following the pipe away from the previous point until it leads back to the start.
+        while let Some(next) = self.neighbours_of(curr).into_iter().find(|&n| n != prev) {
+            if next == start {
+                break;
             }
-            frontier.extend(next_frontier);
-            dist += 1;
+            points.push(next);
+            prev = curr;
+            curr = next;
         }
-        (dist - 1, seen)
+        PipeLoop { points }
+    }
+
+    /// Count tiles enclosed by the loop.
+    fn q2(&self, this_loop: &[Point]) -> u64 {
+        self.enclosed_tiles(this_loop).len() as u64
     }
 
-    fn q2(&self, this_loop: &HashMap<Point, Tile>) -> u64 {
-        let mut enclosed_points = 0;
+    /// Find tiles enclosed by the loop, by scanning each row and flipping between
+    /// inside and outside every time a pipe crosses the row.
+    fn enclosed_tiles(&self, this_loop: &[Point]) -> HashSet<Point> {
+        let this_loop: HashSet<_> = this_loop.iter().collect();
+        let mut enclosed_points = HashSet::new();
         for y in 0..self.0.len() {
-            let pipes_in_row: Vec<_> = this_loop
-                .iter()
-                .filter(|(p, _)| p.y == y)
-                .map(|(p, _)| p)
-                .collect();
             let mut enclosed = false;
-            for x in 0..self.0[0].len() {
+            for x in 0..self.0[y].len() {
                 let p = Point { x, y };
                 let tile = self.at(p).unwrap();
-                if pipes_in_row.contains(&&p) {
+                if this_loop.contains(&p) {
                     if matches!(tile, Tile::SouthEast | Tile::SouthWest | Tile::NorthSouth) {
                         enclosed = !enclosed;
                     }
                 } else if enclosed {
-                    enclosed_points += 1;
+                    enclosed_points.insert(p);
                 }
             }
         }
         enclosed_points
     }
 
+    /// Points which this point's pipe leads to, and whose pipe leads back here.
+    /// Pipes leading off the edge of the grid, or into a pipe which doesn't connect back, are ignored.
     fn neighbours_of(&self, p: Point) -> Vec<Point> {
         let Some(tile) = self.at(p) else {
             return Vec::new();
         };
-        match tile {
-            Tile::NorthSouth => vec![p.north(), p.south()],
-            Tile::EastWest => vec![p.east(), p.west()],
-            Tile::NorthEast => vec![p.north(), p.east()],
-            Tile::NorthWest => vec![p.north(), p.west()],
-            Tile::SouthWest => vec![p.south(), p.west()],
-            Tile::SouthEast => vec![p.south(), p.east()],
-            Tile::Ground => Vec::new(),
-            Tile::Start => panic!("Start should have been replaced by now"),
-        }
+        assert_ne!(tile, Tile::Start, "Start should have been replaced by now");
+        tile.connections()
+            .iter()
+            .filter_map(|dir| {
+                let neighbour = p.step(*dir)?;
+                self.at(neighbour)?
+                    .connects(dir.opposite())
+                    .then_some(neighbour)
+            })
+            .collect()
+    }
+}
+
+/// A loop of pipes, found by walking from the start.
+#[derive(Debug)]
+struct PipeLoop {
+    /// Every point in the loop, in the order they're walked, beginning at the start.
+    points: Vec<Point>,
+}
+
+impl PipeLoop {
+    /// How many tiles make up the loop.
+    fn len(&self) -> usize {
+        self.points.len()
+    }
+
+    /// Walk the loop from the start, following the start's first pipe.
+    fn forwards(&self) -> impl Iterator<Item = Point> + '_ {
+        self.points.iter().copied()
+    }
+
+    /// Walk the loop from the start, following the start's other pipe.
+    fn backwards(&self) -> impl Iterator<Item = Point> + '_ {
+        self.points[..1]
+            .iter()
+            .chain(self.points[1..].iter().rev())
+            .copied()
+    }
+
+    /// How many steps it takes to reach the point farthest from the start, going either way around.
+    fn max_distance(&self) -> u64 {
+        self.len() as u64 / 2
+    }
+
+    /// The point(s) farthest from the start, found by walking both ways around the loop at once.
+    /// If the loop has an odd length the two walks end up on different points, but loops on a
+    /// square grid always have an even length.
+    fn farthest(&self) -> Vec<Point> {
+        let steps = self.max_distance() as usize;
+        let mut points: Vec<_> = self.forwards().nth(steps).into_iter().collect();
+        points.extend(self.backwards().nth(steps).filter(|p| !points.contains(p)));
+        points
     }
 }
 
@@ -99,28 +180,19 @@ struct Point {
 }
 
 impl Point {
-    fn west(&self) -> Self {
-        Self {
-            x: self.x - 1,
-            y: self.y,
-        }
-    }
-    fn east(&self) -> Self {
-        Self {
-            x: self.x + 1,
-            y: self.y,
-        }
-    }
-    fn north(&self) -> Self {
-        Self {
-            x: self.x,
-            y: self.y - 1,
-        }
-    }
-    fn south(&self) -> Self {
-        Self {
-            x: self.x,
-            y: self.y + 1,
+    /// The neighbouring point in that direction, or None if it would have a negative coordinate.
+    fn step(&self, dir: Direction) -> Option<Self> {
+        match dir {
+            Direction::North => self.y.checked_sub(1).map(|y| Self { x: self.x, y }),
+            Direction::South => Some(Self {
+                x: self.x,
+                y: self.y + 1,
+            }),
+            Direction::East => Some(Self {
+                x: self.x + 1,
+                y: self.y,
+            }),
+            Direction::West => self.x.checked_sub(1).map(|x| Self { x, y: self.y }),
         }
     }
 }
@@ -131,16 +203,61 @@ impl std::fmt::Display for Point {
     }
 }
 
-fn find_start(g: &Grid) -> Point {
+/// Count tiles enclosed by a loop, given its points in order.
+/// The shoelace formula gives the loop's area, then Pick's theorem
+/// (area = interior + boundary/2 - 1) gives the number of interior points.
+fn enclosed_by(cycle: &[Point]) -> u64 {
+    let twice_area: i64 = cycle
+        .iter()
+        .zip(cycle.iter().cycle().skip(1))
+        .map(|(p, q)| p.x as i64 * q.y as i64 - q.x as i64 * p.y as i64)
+        .sum();
+    let boundary = cycle.len() as i64;
+    ((twice_area.abs() - boundary + 2) / 2).max(0) as u64
+}
+
+fn find_start(g: &Grid) -> Option<Point> {
     for y in 0..g.0.len() {
         for x in 0..g.0[0].len() {
             let p = Point { x, y };
             if g.at(p) == Some(Tile::Start) {
-                return p;
+                return Some(p);
             }
         }
     }
-    panic!("Never found start");
+    None
+}
+
+/// Why the start tile couldn't be worked out.
+#[derive(Debug, PartialEq, Eq)]
+enum StartError {
+    /// There's no S in the grid.
+    Missing,
+    /// Fewer than two neighbouring pipes connect to the start, so no pipe fits there.
+    Impossible,
+    /// More than two neighbouring pipes connect to the start, so several pipes would fit there.
+    Ambiguous(Vec<Tile>),
+}
+
+#[derive(Eq, PartialEq, Clone, Copy, Debug)]
+enum Direction {
+    North,
+    South,
+    East,
+    West,
+}
+
+impl Direction {
+    const ALL: [Self; 4] = [Self::North, Self::South, Self::East, Self::West];
+
+    fn opposite(self) -> Self {
+        match self {
+            Self::North => Self::South,
+            Self::South => Self::North,
+            Self::East => Self::West,
+            Self::West => Self::East,
+        }
+    }
 }
 
 #[derive(Eq, PartialEq, Clone, Copy, Debug)]
@@ -164,6 +281,34 @@ enum Tile {
 }
 
 impl Tile {
+    /// Every tile which is a pipe.
+    const PIPES: [Self; 6] = [
+        Self::NorthSouth,
+        Self::EastWest,
+        Self::NorthEast,
+        Self::NorthWest,
+        Self::SouthWest,
+        Self::SouthEast,
+    ];
+
+    /// Which directions this tile's pipe leads in.
+    fn connections(self) -> &'static [Direction] {
+        use Direction::*;
+        match self {
+            Self::NorthSouth => &[North, South],
+            Self::EastWest => &[East, West],
+            Self::NorthEast => &[North, East],
+            Self::NorthWest => &[North, West],
+            Self::SouthWest => &[South, West],
+            Self::SouthEast => &[South, East],
+            Self::Ground | Self::Start => &[],
+        }
+    }
+
+    fn connects(self, dir: Direction) -> bool {
+        self.connections().contains(&dir)
+    }
+
     fn parse(c: char) -> Self {
         match c {
             '|' => Self::NorthSouth,
@@ -189,10 +334,114 @@ mod tests {
             (include_str!("../example1.txt"), Tile::SouthEast, 4),
             (include_str!("../example2.txt"), Tile::SouthEast, 8),
         ] {
-            let (grid, start) = Grid::parse(input, start_is);
-            let (actual_q1, this_loop) = grid.loop_containing(start);
-            assert_eq!(actual_q1, expected_q1);
-            grid.q2(&this_loop);
+            let (grid, start) = Grid::parse(input).unwrap();
+            assert_eq!(grid.at(start), Some(start_is));
+            let this_loop = grid.loop_containing(start);
+            assert_eq!(this_loop.max_distance(), expected_q1);
+            assert_eq!(this_loop.points[0], start);
+            assert_eq!(enclosed_by(&this_loop.points), grid.q2(&this_loop.points));
+        }
+    }
+
+    #[test]
+    fn test_enclosed() {
+        for (input, expected_q2) in [
+            (include_str!("../example1.txt"), 1),
+            (include_str!("../example2.txt"), 1),
+            (include_str!("../example3.txt"), 4),
+            (include_str!("../example4.txt"), 4),
+            (include_str!("../example5.txt"), 8),
+            (include_str!("../example6.txt"), 10),
+        ] {
+            let (grid, start) = Grid::parse(input).unwrap();
+            let this_loop = grid.loop_containing(start).points;
+            assert_eq!(grid.q2(&this_loop), expected_q2);
+            assert_eq!(enclosed_by(&this_loop), expected_q2);
         }
     }
+
+    #[test]
+    fn test_infer_start_errors() {
+        assert_eq!(
+            Grid::parse("...\n.|.\n...").err(),
+            Some(StartError::Missing)
+        );
+        // Only the pipe to the east connects to S.
+        assert_eq!(Grid::parse("-S.\n...").err(), Some(StartError::Impossible));
+        // S is on the top row, so nothing can connect from the north.
+        assert_eq!(
+            Grid::parse("S-7\n|.|\nL-J")
+                .unwrap()
+                .0
+                .at(Point { x: 0, y: 0 }),
+            Some(Tile::SouthEast)
+        );
+        // Pipes connect from every side.
+        assert_eq!(
+            Grid::parse(".|.\n-S-\n.|.").err(),
+            Some(StartError::Ambiguous(Tile::PIPES.to_vec()))
+        );
+    }
+
+    #[test]
+    fn test_loop_on_border() {
+        // The loop runs along every edge of the grid.
+        let (grid, start) = Grid::parse("S-7\n|.|\nL-J").unwrap();
+        let this_loop = grid.loop_containing(start);
+        assert_eq!(this_loop.max_distance(), 4);
+        assert_eq!(this_loop.len(), 8);
+        let this_loop = this_loop.points;
+        assert_eq!(grid.q2(&this_loop), 1);
+        assert_eq!(enclosed_by(&this_loop), 1);
+    }
+
+    #[test]
+    fn test_neighbours_must_connect_back() {
+        let (grid, _) = Grid::parse("|S7\n-||\n.LJ").unwrap();
+        // This pipe leads north off the grid, and south into a pipe which doesn't lead back north.
+        let corner = Point { x: 0, y: 0 };
+        assert_eq!(grid.neighbours_of(corner), vec![]);
+        assert_eq!(grid.loop_containing(corner).len(), 1);
+        // This pipe leads west off the grid, and east into a vertical pipe.
+        assert_eq!(grid.neighbours_of(Point { x: 0, y: 1 }), vec![]);
+    }
+
+    #[test]
+    fn test_walk_both_ways() {
+        let (grid, start) = Grid::parse(include_str!("../example1.txt")).unwrap();
+        let this_loop = grid.loop_containing(start);
+        assert_eq!(this_loop.len(), 8);
+        let p = |x, y| Point { x, y };
+        assert_eq!(
+            this_loop.forwards().collect::<Vec<_>>(),
+            vec![
+                p(1, 1),
+                p(1, 2),
+                p(1, 3),
+                p(2, 3),
+                p(3, 3),
+                p(3, 2),
+                p(3, 1),
+                p(2, 1)
+            ]
+        );
+        assert_eq!(
+            this_loop.backwards().collect::<Vec<_>>(),
+            vec![
+                p(1, 1),
+                p(2, 1),
+                p(3, 1),
+                p(3, 2),
+                p(3, 3),
+                p(2, 3),
+                p(1, 3),
+                p(1, 2)
+            ]
+        );
+        assert_eq!(this_loop.farthest(), vec![p(3, 3)]);
+        // Both directions reach the farthest point after the same number of steps.
+        let steps = this_loop.max_distance() as usize;
+        assert_eq!(this_loop.forwards().nth(steps), Some(p(3, 3)));
+        assert_eq!(this_loop.backwards().nth(steps), Some(p(3, 3)));
+    }
 }
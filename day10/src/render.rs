@@ -0,0 +1,103 @@
+use super::{Grid, Point, Tile};
+use std::collections::HashSet;
+
+const DIM: &str = "\x1b[2m";
+const BOLD: &str = "\x1b[1m";
+const GREEN: &str = "\x1b[32m";
+const RESET: &str = "\x1b[0m";
+
+/// How to draw the grid.
+#[derive(Clone, Copy, Debug)]
+pub enum Style {
+    /// Plain text. Pipes which aren't part of the loop are drawn as `·`.
+    Plain,
+    /// ANSI escape codes. Pipes which aren't part of the loop are dimmed, and enclosed tiles are green.
+    Ansi,
+}
+
+impl Grid {
+    /// Draws the grid with box-drawing characters, one line per row.
+    /// The start is drawn as `S` and tiles enclosed by the loop are drawn as `I`.
+    pub fn render(&self, start: Point, this_loop: &[Point], style: Style) -> String {
+        let enclosed = self.enclosed_tiles(this_loop);
+        let this_loop: HashSet<_> = this_loop.iter().collect();
+        let mut out = String::new();
+        for (y, row) in self.0.iter().enumerate() {
+            for (x, tile) in row.iter().enumerate() {
+                let p = Point { x, y };
+                let (colour, c) = if p == start {
+                    (BOLD, 'S')
+                } else if enclosed.contains(&p) {
+                    (GREEN, 'I')
+                } else if this_loop.contains(&p) || *tile == Tile::Ground {
+                    ("", tile.box_char())
+                } else {
+                    match style {
+                        Style::Plain => ("", '·'),
+                        Style::Ansi => (DIM, tile.box_char()),
+                    }
+                };
+                match style {
+                    Style::Ansi if !colour.is_empty() => {
+                        out.push_str(colour);
+                        out.push(c);
+                        out.push_str(RESET);
+                    }
+                    _ => out.push(c),
+                }
+            }
+            out.push('\n');
+        }
+        out
+    }
+}
+
+impl Tile {
+    fn box_char(self) -> char {
+        match self {
+            Self::NorthSouth => '│',
+            Self::EastWest => '─',
+            Self::NorthEast => '└',
+            Self::NorthWest => '┘',
+            Self::SouthWest => '┐',
+            Self::SouthEast => '┌',
+            Self::Ground => ' ',
+            Self::Start => 'S',
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn render(input: &str, style: Style) -> String {
+        let (grid, start) = Grid::parse(input).unwrap();
+        let this_loop = grid.loop_containing(start);
+        grid.render(start, &this_loop.points, style)
+    }
+
+    #[test]
+    fn test_example5_snapshot() {
+        assert_eq!(
+            render(include_str!("../example5.txt"), Style::Plain),
+            include_str!("../example5.plain")
+        );
+    }
+
+    #[test]
+    fn test_example6_snapshot() {
+        assert_eq!(
+            render(include_str!("../example6.txt"), Style::Plain),
+            include_str!("../example6.plain")
+        );
+    }
+
+    #[test]
+    fn test_example6_ansi_snapshot() {
+        assert_eq!(
+            render(include_str!("../example6.txt"), Style::Ansi),
+            include_str!("../example6.ansi")
+        );
+    }
+}
@@ -0,0 +1,304 @@
+use super::{Expansion, Image, Point};
+use std::collections::BTreeSet;
+
+/// A universe whose galaxies can be added and removed, keeping the expansion and the total
+/// distance between galaxies up to date without re-parsing.
+#[allow(dead_code)]
+pub struct DynamicUniverse {
+    /// (y, x) of every galaxy before expansion, so that they iterate in reading order.
+    galaxies: BTreeSet<(usize, usize)>,
+    cols: Axis,
+    rows: Axis,
+}
+
+/// Why a galaxy couldn't be added or removed.
+#[allow(dead_code)]
+#[derive(Debug, PartialEq, Eq)]
+pub enum EditError {
+    /// The point is outside the image.
+    OutOfBounds(Point),
+    /// There's already a galaxy there.
+    AlreadyGalaxy(Point),
+    /// There's no galaxy there to remove.
+    NoGalaxy(Point),
+}
+
+impl DynamicUniverse {
+    #[allow(dead_code)]
+    pub fn parse(s: &str, expansion: &Expansion) -> Self {
+        let image = Image::parse(s);
+        let mut universe = Self {
+            galaxies: BTreeSet::new(),
+            cols: Axis::new((0..image.width).map(|x| expansion.col_factor(x)).collect()),
+            rows: Axis::new((0..image.height).map(|y| expansion.row_factor(y)).collect()),
+        };
+        for (x, y) in image.galaxies {
+            universe.galaxies.insert((y, x));
+            universe.cols.add(x, 1);
+            universe.rows.add(y, 1);
+        }
+        universe
+    }
+
+    /// Adds a galaxy at `p`, given in image coordinates (before expansion).
+    #[allow(dead_code)]
+    pub fn insert(&mut self, p: Point) -> Result<(), EditError> {
+        let (x, y) = self.locate(p)?;
+        if !self.galaxies.insert((y, x)) {
+            return Err(EditError::AlreadyGalaxy(p));
+        }
+        self.cols.add(x, 1);
+        self.rows.add(y, 1);
+        Ok(())
+    }
+
+    /// Removes the galaxy at `p`, given in image coordinates (before expansion).
+    #[allow(dead_code)]
+    pub fn remove(&mut self, p: Point) -> Result<(), EditError> {
+        let (x, y) = self.locate(p)?;
+        if !self.galaxies.remove(&(y, x)) {
+            return Err(EditError::NoGalaxy(p));
+        }
+        self.cols.add(x, -1);
+        self.rows.add(y, -1);
+        Ok(())
+    }
+
+    /// Sum of the distances between every pair of galaxies, like `Universe::q1`.
+    #[allow(dead_code)]
+    pub fn total_distance(&self) -> u128 {
+        self.cols.total_distance() + self.rows.total_distance()
+    }
+
+    /// Where a point in the image ends up after expansion.
+    #[allow(dead_code)]
+    pub fn expand(&self, p: Point) -> Result<Point, EditError> {
+        let (x, y) = self.locate(p)?;
+        Ok(Point {
+            x: self.cols.expanded(x),
+            y: self.rows.expanded(y),
+        })
+    }
+
+    /// Every galaxy after expansion, in reading order, like `Universe::galaxies`.
+    #[cfg(test)]
+    pub fn galaxies(&self) -> Vec<Point> {
+        self.galaxies
+            .iter()
+            .map(|&(y, x)| Point {
+                x: self.cols.expanded(x),
+                y: self.rows.expanded(y),
+            })
+            .collect()
+    }
+
+    fn locate(&self, p: Point) -> Result<(usize, usize), EditError> {
+        let in_bounds = |coord: u128, len: usize| usize::try_from(coord).ok().filter(|&i| i < len);
+        match (
+            in_bounds(p.x, self.cols.len()),
+            in_bounds(p.y, self.rows.len()),
+        ) {
+            (Some(x), Some(y)) => Ok((x, y)),
+            _ => Err(EditError::OutOfBounds(p)),
+        }
+    }
+}
+
+/// Every column (or every row) of the image, in a segment tree.
+/// Each node summarises a run of lines, so adding or removing a galaxy only updates the nodes
+/// above its line.
+struct Axis {
+    /// How many lines each line becomes if it's empty.
+    factors: Vec<u128>,
+    /// How many galaxies are on each line.
+    counts: Vec<u128>,
+    /// Node 1 is the root and node i has children 2i and 2i + 1. Lines are the leaves, starting
+    /// at `leaves`. Leaves past the last line are empty spans, which change nothing.
+    tree: Vec<Span>,
+    leaves: usize,
+}
+
+impl Axis {
+    fn new(factors: Vec<u128>) -> Self {
+        let leaves = factors.len().next_power_of_two();
+        let mut axis = Self {
+            counts: vec![0; factors.len()],
+            factors,
+            tree: vec![Span::default(); 2 * leaves],
+            leaves,
+        };
+        for i in 0..axis.len() {
+            axis.tree[leaves + i] = axis.leaf(i);
+        }
+        for node in (1..leaves).rev() {
+            axis.tree[node] = axis.tree[2 * node].then(axis.tree[2 * node + 1]);
+        }
+        axis
+    }
+
+    fn len(&self) -> usize {
+        self.counts.len()
+    }
+
+    fn leaf(&self, i: usize) -> Span {
+        let galaxies = self.counts[i];
+        Span {
+            galaxies,
+            width: if galaxies == 0 { self.factors[i] } else { 1 },
+            offsets: 0,
+            distances: 0,
+        }
+    }
+
+    /// Adds (or with a negative `delta`, removes) galaxies on line `i`.
+    fn add(&mut self, i: usize, delta: i8) {
+        self.counts[i] = self.counts[i].checked_add_signed(delta.into()).unwrap();
+        let mut node = self.leaves + i;
+        self.tree[node] = self.leaf(i);
+        while node > 1 {
+            node /= 2;
+            self.tree[node] = self.tree[2 * node].then(self.tree[2 * node + 1]);
+        }
+    }
+
+    fn total_distance(&self) -> u128 {
+        self.tree[1].distances
+    }
+
+    /// Where line `i` ends up after expansion, i.e. the width of every line before it.
+    fn expanded(&self, i: usize) -> u128 {
+        // Walk down from the root, adding up every left sibling of the path to the leaf.
+        let mut width = 0;
+        let mut node = 1;
+        let mut lo = 0;
+        let mut size = self.leaves;
+        while size > 1 {
+            size /= 2;
+            if i < lo + size {
+                node *= 2;
+            } else {
+                width += self.tree[2 * node].width;
+                node = 2 * node + 1;
+                lo += size;
+            }
+        }
+        width
+    }
+}
+
+/// A run of consecutive lines, after expansion.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+struct Span {
+    /// How many galaxies lie in the span.
+    galaxies: u128,
+    /// How wide the span is.
+    width: u128,
+    /// Sum of each galaxy's distance from the start of the span.
+    offsets: u128,
+    /// Sum of the distances between every pair of galaxies in the span.
+    distances: u128,
+}
+
+impl Span {
+    /// This span followed by the `next` one.
+    fn then(self, next: Self) -> Self {
+        // Galaxies in `next` are `self.width` further from the start of the combined span.
+        let next_offsets = next.offsets + next.galaxies * self.width;
+        Self {
+            galaxies: self.galaxies + next.galaxies,
+            width: self.width + next.width,
+            offsets: self.offsets + next_offsets,
+            // Pairs split across the two spans are the sum of (next galaxy - this galaxy).
+            distances: self.distances + next.distances + self.galaxies * next_offsets
+                - next.galaxies * self.offsets,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Universe;
+    use pretty_assertions::assert_eq;
+
+    /// Draws the image, so it can be checked against parsing from scratch.
+    fn draw(universe: &DynamicUniverse) -> String {
+        let mut image = vec![vec!['.'; universe.cols.len()]; universe.rows.len()];
+        for &(y, x) in &universe.galaxies {
+            image[y][x] = '#';
+        }
+        image
+            .into_iter()
+            .map(|row| row.into_iter().collect::<String>() + "\n")
+            .collect()
+    }
+
+    #[test]
+    fn test_matches_parse() {
+        let input = include_str!("../example.txt");
+        for factor in [1, 2, 10, 100] {
+            let expansion = Expansion::uniform(factor).unwrap();
+            let universe = DynamicUniverse::parse(input, &expansion);
+            let expected = Universe::parse_with(input, &expansion);
+            assert_eq!(universe.galaxies(), expected.galaxies);
+            assert_eq!(universe.total_distance(), expected.q1());
+        }
+    }
+
+    #[test]
+    fn test_edits() {
+        let expansion = Expansion::new(3, 7)
+            .and_then(|e| e.with_row_factor(7, 1_000))
+            .unwrap();
+        let mut universe = DynamicUniverse::parse(include_str!("../example.txt"), &expansion);
+        let p = |x, y| Point { x, y };
+        // Fill and empty some lines, including the edges of the image.
+        let edits = [
+            (true, p(2, 3)),
+            (true, p(5, 7)),
+            (false, p(3, 0)),
+            (false, p(0, 2)),
+            (true, p(9, 9)),
+            (false, p(2, 3)),
+            (false, p(0, 9)),
+            (false, p(4, 9)),
+            (true, p(0, 0)),
+            (false, p(7, 8)),
+            (false, p(9, 9)),
+        ];
+        for (insert, point) in edits {
+            if insert {
+                universe.insert(point).unwrap();
+            } else {
+                universe.remove(point).unwrap();
+            }
+            let expected = Universe::parse_with(&draw(&universe), &expansion);
+            assert_eq!(universe.galaxies(), expected.galaxies);
+            assert_eq!(universe.total_distance(), expected.q1());
+        }
+    }
+
+    #[test]
+    fn test_edit_errors() {
+        let mut universe = DynamicUniverse::parse(
+            include_str!("../example.txt"),
+            &Expansion::uniform(2).unwrap(),
+        );
+        let p = |x, y| Point { x, y };
+        assert_eq!(
+            universe.insert(p(3, 0)),
+            Err(EditError::AlreadyGalaxy(p(3, 0)))
+        );
+        assert_eq!(universe.remove(p(0, 0)), Err(EditError::NoGalaxy(p(0, 0))));
+        assert_eq!(
+            universe.insert(p(10, 0)),
+            Err(EditError::OutOfBounds(p(10, 0)))
+        );
+        assert_eq!(
+            universe.expand(p(0, 10)),
+            Err(EditError::OutOfBounds(p(0, 10)))
+        );
+        // Column 2 and row 3 are empty, so they doubled.
+        assert_eq!(universe.expand(p(3, 4)), Ok(p(4, 5)));
+    }
+}
@@ -1,4 +1,7 @@
-use rustc_hash::FxHashSet as HashSet;
+use std::collections::HashMap;
+
+mod dynamic;
+mod query;
 
 fn main() {
     let input = include_str!("../input.txt");
@@ -14,64 +17,186 @@ struct Universe {
 }
 
 impl Universe {
-    fn q1(&self) -> usize {
-        let mut total_distances = 0;
-        for i in 0..self.galaxies.len() {
-            for j in (i + 1)..self.galaxies.len() {
-                total_distances += self.galaxies[i].distance_to(self.galaxies[j]);
-            }
+    /// Sum of the distances between every pair of galaxies.
+    /// Manhattan distance splits into independent x and y parts, and each part can be summed
+    /// over all pairs at once by sorting the coordinates.
+    fn q1(&self) -> u128 {
+        sum_of_pairwise_gaps(self.galaxies.iter().map(|p| p.x).collect())
+            + sum_of_pairwise_gaps(self.galaxies.iter().map(|p| p.y).collect())
+    }
+
+    /// Every empty row and column expands by the same factor.
+    /// Panics if the factor is 0.
+    fn parse(s: &str, expansion_factor: u128) -> Self {
+        let expansion = Expansion::uniform(expansion_factor).unwrap_or_else(|err| panic!("{err}"));
+        Self::parse_with(s, &expansion)
+    }
+
+    fn parse_with(s: &str, expansion: &Expansion) -> Self {
+        let Image {
+            galaxies: galaxies_before_expansion,
+            width,
+            height,
+        } = Image::parse(s);
+        let mut occupied_cols = vec![false; width];
+        let mut occupied_rows = vec![false; height];
+        for &(x, y) in &galaxies_before_expansion {
+            occupied_cols[x] = true;
+            occupied_rows[y] = true;
         }
-        total_distances
+        let extra_cols_before = extra_before(&occupied_cols, |x| expansion.col_factor(x));
+        let extra_rows_before = extra_before(&occupied_rows, |y| expansion.row_factor(y));
+        let galaxies = galaxies_before_expansion
+            .into_iter()
+            .map(|(x, y)| Point {
+                x: x as u128 + extra_cols_before[x],
+                y: y as u128 + extra_rows_before[y],
+            })
+            .collect();
+        Self { galaxies }
     }
-    fn parse(s: &str, expansion_factor: usize) -> Self {
-        let mut galaxies_before_expansion = HashSet::default();
-        let lines = s.lines().collect::<Vec<_>>();
+}
+
+/// The galaxies in the image, before expansion.
+struct Image {
+    /// (x, y) of every galaxy, in reading order.
+    galaxies: Vec<(usize, usize)>,
+    width: usize,
+    height: usize,
+}
+
+impl Image {
+    fn parse(s: &str) -> Self {
+        let mut galaxies = Vec::new();
         let mut width = 0;
-        let height = lines.len();
-        for (y, row) in lines.into_iter().enumerate() {
-            width = row.len();
+        let mut height = 0;
+        for (y, row) in s.lines().enumerate() {
+            width = width.max(row.len());
+            height = y + 1;
             for (x, ch) in row.chars().enumerate() {
                 if ch == '#' {
-                    galaxies_before_expansion.insert(Point { x, y });
+                    galaxies.push((x, y));
                 }
             }
         }
-        let expanding_rows: Vec<_> = (0..height)
-            .filter(|y| {
-                let has_galaxy =
-                    (0..width).any(|x| galaxies_before_expansion.contains(&Point { x, y: *y }));
-                !has_galaxy
-            })
-            .collect();
-        let expanding_cols: Vec<_> = (0..width)
-            .filter(|x| {
-                let has_galaxy =
-                    (0..height).any(|y| galaxies_before_expansion.contains(&Point { x: *x, y }));
-                !has_galaxy
-            })
-            .collect();
-        let galaxies = galaxies_before_expansion
-            .into_iter()
-            .map(|mut galaxy| {
-                galaxy.x += expanding_cols.iter().filter(|x| x < &&galaxy.x).count()
-                    * (expansion_factor - 1);
-                galaxy.y += expanding_rows.iter().filter(|y| y < &&galaxy.y).count()
-                    * (expansion_factor - 1);
-                galaxy
-            })
-            .collect();
-        Self { galaxies }
+        Self {
+            galaxies,
+            width,
+            height,
+        }
     }
 }
 
+/// How many rows or columns each empty row or column becomes.
+#[derive(Debug, Clone)]
+struct Expansion {
+    /// Factor for empty columns, i.e. how much the universe stretches horizontally.
+    horizontal: u128,
+    /// Factor for empty rows, i.e. how much the universe stretches vertically.
+    vertical: u128,
+    /// Overrides `horizontal` for particular columns, indexed before expansion.
+    col_factors: HashMap<usize, u128>,
+    /// Overrides `vertical` for particular rows, indexed before expansion.
+    row_factors: HashMap<usize, u128>,
+}
+
+/// Why an expansion can't be used.
+#[derive(Debug, PartialEq, Eq)]
+enum ExpansionError {
+    /// Empty lines can't shrink away to nothing, so every factor must be at least 1.
+    ZeroFactor,
+}
+
+impl std::fmt::Display for ExpansionError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::ZeroFactor => write!(f, "expansion factors must be at least 1"),
+        }
+    }
+}
+
+fn check_factor(factor: u128) -> Result<u128, ExpansionError> {
+    if factor == 0 {
+        Err(ExpansionError::ZeroFactor)
+    } else {
+        Ok(factor)
+    }
+}
+
+impl Expansion {
+    fn uniform(factor: u128) -> Result<Self, ExpansionError> {
+        Self::new(factor, factor)
+    }
+
+    fn new(horizontal: u128, vertical: u128) -> Result<Self, ExpansionError> {
+        Ok(Self {
+            horizontal: check_factor(horizontal)?,
+            vertical: check_factor(vertical)?,
+            col_factors: HashMap::new(),
+            row_factors: HashMap::new(),
+        })
+    }
+
+    /// Empty column `x` expands by `factor` instead of the horizontal factor.
+    #[allow(dead_code)]
+    fn with_col_factor(mut self, x: usize, factor: u128) -> Result<Self, ExpansionError> {
+        self.col_factors.insert(x, check_factor(factor)?);
+        Ok(self)
+    }
+
+    /// Empty row `y` expands by `factor` instead of the vertical factor.
+    #[allow(dead_code)]
+    fn with_row_factor(mut self, y: usize, factor: u128) -> Result<Self, ExpansionError> {
+        self.row_factors.insert(y, check_factor(factor)?);
+        Ok(self)
+    }
+
+    fn col_factor(&self, x: usize) -> u128 {
+        self.col_factors.get(&x).copied().unwrap_or(self.horizontal)
+    }
+
+    fn row_factor(&self, y: usize) -> u128 {
+        self.row_factors.get(&y).copied().unwrap_or(self.vertical)
+    }
+}
+
+/// For each line, how many extra lines the empty lines before it expand into.
+fn extra_before(occupied: &[bool], factor: impl Fn(usize) -> u128) -> Vec<u128> {
+    occupied
+        .iter()
+        .enumerate()
+        .scan(0, |extra_so_far, (i, &occupied)| {
+            let before = *extra_so_far;
+            if !occupied {
+                *extra_so_far += factor(i) - 1;
+            }
+            Some(before)
+        })
+        .collect()
+}
+
+/// Sum of |a - b| over every pair of values.
+/// Once sorted, each value is bigger than everything before it, so it contributes
+/// (value * number of earlier values) - (sum of earlier values).
+fn sum_of_pairwise_gaps(mut values: Vec<u128>) -> u128 {
+    values.sort_unstable();
+    let mut total = 0;
+    let mut sum_before = 0;
+    for (i, value) in values.into_iter().enumerate() {
+        total += value * i as u128 - sum_before;
+        sum_before += value;
+    }
+    total
+}
+
 #[derive(Clone, Copy, Debug, Eq, PartialEq, Hash, Ord, PartialOrd)]
 struct Point {
-    x: usize,
-    y: usize,
+    x: u128,
+    y: u128,
 }
 
 impl Point {
-    fn distance_to(&self, other: Point) -> usize {
+    fn distance_to(&self, other: Point) -> u128 {
         self.x.abs_diff(other.x) + self.y.abs_diff(other.y)
     }
 }
@@ -109,4 +234,94 @@ mod tests {
             assert_eq!(universe.q1(), expected);
         }
     }
+
+    /// A scattered universe with plenty of empty rows and columns.
+    pub(crate) fn scattered() -> String {
+        (0..60)
+            .map(|y| {
+                (0..80)
+                    .map(|x| {
+                        if (x * 7 + y * 13) % 29 == 0 && y % 5 != 2 {
+                            '#'
+                        } else {
+                            '.'
+                        }
+                    })
+                    .collect::<String>()
+                    + "\n"
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_matches_every_pair() {
+        let input = scattered();
+        for expansion_factor in [1, 2, 1_000] {
+            let universe = Universe::parse(&input, expansion_factor);
+            let galaxies = &universe.galaxies;
+            let mut expected = 0;
+            for i in 0..galaxies.len() {
+                for j in (i + 1)..galaxies.len() {
+                    expected += galaxies[i].distance_to(galaxies[j]);
+                }
+            }
+            assert_eq!(universe.q1(), expected);
+        }
+    }
+
+    #[test]
+    fn test_separate_factors() {
+        let input = include_str!("../example.txt");
+        // Empty columns are 2, 5 and 8. Empty rows are 3 and 7.
+        let expansion = Expansion::new(2, 10)
+            .and_then(|e| e.with_col_factor(8, 1))
+            .and_then(|e| e.with_row_factor(7, 100))
+            .unwrap();
+        let mut actual = Universe::parse_with(input, &expansion).galaxies;
+        actual.sort_by_key(|p| (p.y, p.x));
+        let expected_galaxies = vec![
+            Point { x: 4, y: 0 },
+            Point { x: 9, y: 1 },
+            Point { x: 0, y: 2 },
+            Point { x: 8, y: 13 },
+            Point { x: 1, y: 14 },
+            Point { x: 11, y: 15 },
+            Point { x: 9, y: 116 },
+            Point { x: 0, y: 117 },
+            Point { x: 5, y: 117 },
+        ];
+        assert_eq!(expected_galaxies, actual);
+
+        // Only stretching one axis.
+        let sideways = Universe::parse_with(input, &Expansion::new(10, 1).unwrap()).q1();
+        let upwards = Universe::parse_with(input, &Expansion::new(1, 10).unwrap()).q1();
+        let neither = Universe::parse(input, 1).q1();
+        let both = Universe::parse(input, 10).q1();
+        assert_eq!(sideways + upwards, neither + both);
+    }
+
+    #[test]
+    fn test_zero_factor() {
+        assert_eq!(
+            Expansion::uniform(0).err(),
+            Some(ExpansionError::ZeroFactor)
+        );
+        assert_eq!(Expansion::new(2, 0).err(), Some(ExpansionError::ZeroFactor));
+        let expansion = Expansion::uniform(2).unwrap();
+        assert_eq!(
+            expansion.clone().with_col_factor(3, 0).err(),
+            Some(ExpansionError::ZeroFactor)
+        );
+        assert_eq!(
+            expansion.with_row_factor(3, 0).err(),
+            Some(ExpansionError::ZeroFactor)
+        );
+    }
+
+    #[test]
+    fn test_huge_factor() {
+        // The total grows by 82 for every extra line each empty line expands into.
+        let universe = Universe::parse(include_str!("../example.txt"), 10u128.pow(20));
+        assert_eq!(universe.q1(), 292 + 82 * (10u128.pow(20) - 1));
+    }
 }
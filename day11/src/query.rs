@@ -0,0 +1,422 @@
+use super::{Point, Universe};
+use std::collections::BinaryHeap;
+
+/// How to measure the distance between two galaxies.
+#[allow(dead_code)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Metric {
+    /// Steps along rows and columns, i.e. |dx| + |dy|.
+    Manhattan,
+    /// Steps where diagonal moves are allowed, i.e. max(|dx|, |dy|).
+    Chebyshev,
+    /// dx² + dy². Saturates at u128::MAX for universes too big to square.
+    SquaredEuclidean,
+}
+
+impl Metric {
+    #[allow(dead_code)]
+    pub const ALL: [Self; 3] = [Self::Manhattan, Self::Chebyshev, Self::SquaredEuclidean];
+
+    pub fn distance(self, a: Point, b: Point) -> u128 {
+        let dx = a.x.abs_diff(b.x);
+        let dy = a.y.abs_diff(b.y);
+        match self {
+            Self::Manhattan => a.distance_to(b),
+            Self::Chebyshev => dx.max(dy),
+            Self::SquaredEuclidean => dx.saturating_mul(dx).saturating_add(dy.saturating_mul(dy)),
+        }
+    }
+
+    /// The shortest possible distance between two points which are `d` apart along one axis.
+    fn axis_bound(self, d: u128) -> u128 {
+        match self {
+            Self::Manhattan | Self::Chebyshev => d,
+            Self::SquaredEuclidean => d.saturating_mul(d),
+        }
+    }
+}
+
+/// A galaxy found by a query. Galaxies are identified by their index in `Universe::galaxies`,
+/// which is the order they appear in the image, reading left-to-right then top-to-bottom.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Neighbour {
+    pub distance: u128,
+    pub id: usize,
+}
+
+/// Two galaxies, and the distance between them. The smaller id comes first.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Pair {
+    pub ids: (usize, usize),
+    pub distance: u128,
+}
+
+impl Pair {
+    fn new(galaxies: &[Point], a: usize, b: usize, metric: Metric) -> Self {
+        Self {
+            ids: (a.min(b), a.max(b)),
+            distance: metric.distance(galaxies[a], galaxies[b]),
+        }
+    }
+}
+
+/// A k-d tree over a universe's galaxies, for finding galaxies near a point.
+pub struct SpatialIndex<'a> {
+    galaxies: &'a [Point],
+    /// Galaxy ids arranged as an implicit k-d tree. The middle of each slice splits the rest of
+    /// the slice in two, by x on even levels and by y on odd levels.
+    tree: Vec<usize>,
+}
+
+impl<'a> SpatialIndex<'a> {
+    pub fn new(galaxies: &'a [Point]) -> Self {
+        let mut tree: Vec<_> = (0..galaxies.len()).collect();
+        build(galaxies, &mut tree, 0);
+        Self { galaxies, tree }
+    }
+
+    /// The closest galaxy to `query`, if there are any galaxies.
+    #[allow(dead_code)]
+    pub fn nearest(&self, query: Point, metric: Metric) -> Option<Neighbour> {
+        self.k_nearest(query, 1, metric).pop()
+    }
+
+    /// The `k` closest galaxies to `query`, closest first. Ties are broken by id.
+    pub fn k_nearest(&self, query: Point, k: usize, metric: Metric) -> Vec<Neighbour> {
+        if k == 0 {
+            return Vec::new();
+        }
+        let mut nearest = KNearest {
+            k,
+            found: BinaryHeap::new(),
+        };
+        self.search(&self.tree, 0, query, metric, &mut nearest);
+        nearest.found.into_sorted_vec()
+    }
+
+    /// Every galaxy at most `radius` from `query`, closest first. Ties are broken by id.
+    #[allow(dead_code)]
+    pub fn within(&self, query: Point, radius: u128, metric: Metric) -> Vec<Neighbour> {
+        let mut within = Within {
+            radius,
+            found: Vec::new(),
+        };
+        self.search(&self.tree, 0, query, metric, &mut within);
+        within.found.sort_unstable();
+        within.found
+    }
+
+    fn search(
+        &self,
+        ids: &[usize],
+        depth: usize,
+        query: Point,
+        metric: Metric,
+        collector: &mut impl Collector,
+    ) {
+        if ids.is_empty() {
+            return;
+        }
+        let mid = ids.len() / 2;
+        let id = ids[mid];
+        collector.offer(Neighbour {
+            distance: metric.distance(query, self.galaxies[id]),
+            id,
+        });
+        let q = coordinate(query, depth);
+        let split = coordinate(self.galaxies[id], depth);
+        let (near, far) = if q < split {
+            (&ids[..mid], &ids[mid + 1..])
+        } else {
+            (&ids[mid + 1..], &ids[..mid])
+        };
+        self.search(near, depth + 1, query, metric, collector);
+        // Everything on the far side is at least this far away, so only look there if it could
+        // beat what's been found already.
+        let closest_possible = metric.axis_bound(q.abs_diff(split));
+        if collector
+            .radius()
+            .is_none_or(|radius| closest_possible <= radius)
+        {
+            self.search(far, depth + 1, query, metric, collector);
+        }
+    }
+}
+
+/// Sorts `ids` into an implicit k-d tree.
+fn build(galaxies: &[Point], ids: &mut [usize], depth: usize) {
+    if ids.len() <= 1 {
+        return;
+    }
+    let mid = ids.len() / 2;
+    ids.select_nth_unstable_by_key(mid, |&id| coordinate(galaxies[id], depth));
+    let (left, right) = ids.split_at_mut(mid);
+    build(galaxies, left, depth + 1);
+    build(galaxies, &mut right[1..], depth + 1);
+}
+
+/// The coordinate which the k-d tree splits on at this depth.
+fn coordinate(p: Point, depth: usize) -> u128 {
+    if depth.is_multiple_of(2) {
+        p.x
+    } else {
+        p.y
+    }
+}
+
+/// Gathers galaxies during a k-d tree search.
+trait Collector {
+    fn offer(&mut self, neighbour: Neighbour);
+    /// Galaxies further than this can't be collected, so the search can skip them.
+    /// None if any galaxy could still be collected.
+    fn radius(&self) -> Option<u128>;
+}
+
+struct KNearest {
+    k: usize,
+    /// Max-heap, so the furthest of the nearest galaxies can be swapped out.
+    found: BinaryHeap<Neighbour>,
+}
+
+impl Collector for KNearest {
+    fn offer(&mut self, neighbour: Neighbour) {
+        if self.found.len() < self.k {
+            self.found.push(neighbour);
+        } else if self.found.peek().is_some_and(|worst| neighbour < *worst) {
+            self.found.pop();
+            self.found.push(neighbour);
+        }
+    }
+
+    fn radius(&self) -> Option<u128> {
+        if self.found.len() < self.k {
+            None
+        } else {
+            self.found.peek().map(|worst| worst.distance)
+        }
+    }
+}
+
+struct Within {
+    radius: u128,
+    found: Vec<Neighbour>,
+}
+
+impl Collector for Within {
+    fn offer(&mut self, neighbour: Neighbour) {
+        if neighbour.distance <= self.radius {
+            self.found.push(neighbour);
+        }
+    }
+
+    fn radius(&self) -> Option<u128> {
+        Some(self.radius)
+    }
+}
+
+impl Universe {
+    #[allow(dead_code)]
+    pub fn index(&self) -> SpatialIndex<'_> {
+        SpatialIndex::new(&self.galaxies)
+    }
+
+    /// The two galaxies closest to each other. Ties are broken by id.
+    #[allow(dead_code)]
+    pub fn closest_pair(&self, metric: Metric) -> Option<Pair> {
+        let index = self.index();
+        self.galaxies
+            .iter()
+            .enumerate()
+            .filter_map(|(id, galaxy)| {
+                // The nearest galaxy is this one, so look at the next nearest.
+                let other = index
+                    .k_nearest(*galaxy, 2, metric)
+                    .into_iter()
+                    .find(|neighbour| neighbour.id != id)?;
+                Some(Pair::new(&self.galaxies, id, other.id, metric))
+            })
+            .min_by_key(|pair| (pair.distance, pair.ids))
+    }
+
+    /// The two galaxies furthest from each other. Ties are broken by id.
+    #[allow(dead_code)]
+    pub fn farthest_pair(&self, metric: Metric) -> Option<Pair> {
+        if self.galaxies.len() < 2 {
+            return None;
+        }
+        let ids = 0..self.galaxies.len();
+        let candidates = match metric {
+            // |dx| + |dy| is the bigger of |d(x + y)| and |d(x - y)|, so the farthest pair
+            // is at the extremes of one of those diagonals.
+            Metric::Manhattan => {
+                let max_y = self.galaxies.iter().map(|p| p.y).max().unwrap();
+                vec![
+                    extremes(ids.clone(), |id| self.galaxies[id].x + self.galaxies[id].y),
+                    extremes(ids, |id| self.galaxies[id].x + max_y - self.galaxies[id].y),
+                ]
+            }
+            Metric::Chebyshev => vec![
+                extremes(ids.clone(), |id| self.galaxies[id].x),
+                extremes(ids, |id| self.galaxies[id].y),
+            ],
+            // The farthest pair are both corners of the convex hull.
+            Metric::SquaredEuclidean => {
+                let hull = convex_hull(&self.galaxies);
+                hull.iter()
+                    .enumerate()
+                    .flat_map(|(i, &a)| hull[i + 1..].iter().map(move |&b| (a, b)))
+                    .collect()
+            }
+        };
+        candidates
+            .into_iter()
+            .map(|(a, b)| Pair::new(&self.galaxies, a, b, metric))
+            .max_by_key(|pair| (pair.distance, std::cmp::Reverse(pair.ids)))
+    }
+}
+
+/// The lowest ids with the smallest and biggest key.
+/// Any pair with one galaxy at each extreme is equally far apart along the key,
+/// so pairing the lowest id at each end gives the pair with the lowest ids.
+fn extremes(
+    ids: impl Iterator<Item = usize> + Clone,
+    key: impl Fn(usize) -> u128,
+) -> (usize, usize) {
+    let min = ids.clone().min_by_key(|&id| key(id)).unwrap();
+    let max = ids.min_by_key(|&id| std::cmp::Reverse(key(id))).unwrap();
+    (min, max)
+}
+
+/// Ids of the galaxies at the corners of the convex hull, using Andrew's monotone chain.
+/// Assumes coordinates fit in an i128.
+fn convex_hull(galaxies: &[Point]) -> Vec<usize> {
+    let mut ids: Vec<_> = (0..galaxies.len()).collect();
+    ids.sort_unstable_by_key(|&id| (galaxies[id].x, galaxies[id].y));
+    if ids.len() <= 2 {
+        return ids;
+    }
+    // Positive if a -> b -> c turns anticlockwise.
+    let cross = |a: usize, b: usize, c: usize| {
+        let (a, b, c) = (galaxies[a], galaxies[b], galaxies[c]);
+        let (abx, aby) = (b.x as i128 - a.x as i128, b.y as i128 - a.y as i128);
+        let (acx, acy) = (c.x as i128 - a.x as i128, c.y as i128 - a.y as i128);
+        abx * acy - aby * acx
+    };
+    let mut hull: Vec<usize> = Vec::with_capacity(ids.len() + 1);
+    for pass in [ids.clone(), ids.into_iter().rev().collect()] {
+        let floor = hull.len();
+        for id in pass {
+            while hull.len() >= floor + 2
+                && cross(hull[hull.len() - 2], hull[hull.len() - 1], id) <= 0
+            {
+                hull.pop();
+            }
+            hull.push(id);
+        }
+        // The last point of each pass is the first point of the next.
+        hull.pop();
+    }
+    hull
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    fn example() -> Universe {
+        Universe::parse(include_str!("../example.txt"), 2)
+    }
+
+    /// A scattered universe, big enough for the k-d tree to have a few levels.
+    fn scattered() -> Universe {
+        Universe::parse(&crate::tests::scattered(), 3)
+    }
+
+    fn every_pair(universe: &Universe, metric: Metric) -> Vec<Pair> {
+        let n = universe.galaxies.len();
+        (0..n)
+            .flat_map(|a| ((a + 1)..n).map(move |b| (a, b)))
+            .map(|(a, b)| Pair::new(&universe.galaxies, a, b, metric))
+            .collect()
+    }
+
+    #[test]
+    fn test_metrics() {
+        let galaxies = example().galaxies;
+        // Galaxies 5 and 9 from the puzzle.
+        let (a, b) = (galaxies[4], galaxies[8]);
+        assert_eq!(Metric::Manhattan.distance(a, b), 9);
+        assert_eq!(Metric::Chebyshev.distance(a, b), 5);
+        assert_eq!(Metric::SquaredEuclidean.distance(a, b), 41);
+    }
+
+    #[test]
+    fn test_queries_match_brute_force() {
+        for universe in [example(), scattered()] {
+            let index = universe.index();
+            for metric in Metric::ALL {
+                for query in [
+                    Point { x: 0, y: 0 },
+                    Point { x: 7, y: 3 },
+                    Point { x: 50, y: 40 },
+                    universe.galaxies[1],
+                ] {
+                    let mut expected: Vec<_> = universe
+                        .galaxies
+                        .iter()
+                        .enumerate()
+                        .map(|(id, galaxy)| Neighbour {
+                            distance: metric.distance(query, *galaxy),
+                            id,
+                        })
+                        .collect();
+                    expected.sort();
+                    assert_eq!(index.nearest(query, metric), expected.first().copied());
+                    for k in [0, 1, 3, 10] {
+                        let k_nearest = index.k_nearest(query, k, metric);
+                        assert_eq!(k_nearest, expected[..k.min(expected.len())]);
+                    }
+                    for radius in [0, 5, 30] {
+                        let within: Vec<_> = expected
+                            .iter()
+                            .copied()
+                            .filter(|n| n.distance <= radius)
+                            .collect();
+                        assert_eq!(index.within(query, radius, metric), within);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_pairs_match_brute_force() {
+        for universe in [example(), scattered()] {
+            for metric in Metric::ALL {
+                let pairs = every_pair(&universe, metric);
+                let closest = pairs.iter().min_by_key(|p| (p.distance, p.ids)).copied();
+                assert_eq!(universe.closest_pair(metric), closest);
+                let farthest = pairs
+                    .iter()
+                    .max_by_key(|p| (p.distance, std::cmp::Reverse(p.ids)))
+                    .copied();
+                assert_eq!(universe.farthest_pair(metric), farthest);
+            }
+        }
+    }
+
+    #[test]
+    fn test_too_few_galaxies() {
+        let universe = Universe::parse("...\n.#.\n...", 2);
+        assert_eq!(universe.closest_pair(Metric::Manhattan), None);
+        assert_eq!(universe.farthest_pair(Metric::Manhattan), None);
+        let universe = Universe::parse("...", 2);
+        assert_eq!(
+            universe
+                .index()
+                .nearest(Point { x: 0, y: 0 }, Metric::Chebyshev),
+            None
+        );
+    }
+}
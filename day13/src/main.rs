@@ -2,18 +2,23 @@ fn main() {
     let grids = parse_grids(include_str!("../input.txt"));
     let q1: usize = grids
         .iter()
-        .map(|grid| Reflection::of_q1(grid, None).unwrap().score())
+        .map(|grid| reflection_with_smudges(grid, 0).unwrap().score())
         .sum();
     println!("Q1: {q1}");
     let q2: usize = grids
-        .into_iter()
-        .map(|grid| Reflection::of_q2(grid).score())
+        .iter()
+        .map(|grid| reflection_with_smudges(grid, 1).unwrap().score())
         .sum();
     println!("Q2: {q2}");
 }
 
+/// Each row and column is stored as a bitmask, where bit i is set if the i'th cell is a rock (#).
+/// Lines longer than 64 cells are split across several words.
 #[derive(Debug)]
-struct Grid(Vec<Vec<char>>);
+struct Grid {
+    rows: Vec<Vec<u64>>,
+    cols: Vec<Vec<u64>>,
+}
 
 #[derive(Debug, PartialEq, Eq)]
 enum Reflection {
@@ -27,92 +32,55 @@ fn parse_grids(s: &str) -> Vec<Grid> {
 
 impl Grid {
     fn parse(s: &str) -> Self {
-        Self(s.lines().map(|line| line.chars().collect()).collect())
-    }
-    fn row(&self, i: usize) -> &[char] {
-        &self.0[i]
-    }
-    fn col(&self, i: usize) -> Vec<char> {
-        (0..self.0.len()).map(|y| self.0[y][i]).collect()
-    }
-    fn width(&self) -> usize {
-        self.0[0].len()
-    }
-    fn height(&self) -> usize {
-        self.0.len()
-    }
-
-    fn smudge(&mut self, x: usize, y: usize) {
-        self.0[y][x] = if self.0[y][x] == '.' { '#' } else { '.' }
-    }
-}
-
-impl Reflection {
-    fn of_q1(grid: &Grid, excluding: Option<&Reflection>) -> Option<Self> {
-        // Try every row
-        let height = grid.height();
-        for y in 0..height - 1 {
-            let mut rows_different = false;
-            let mut a = y as isize;
-            let mut b = y as isize + 1;
-            while a >= 0 && b < (height as isize) {
-                if grid.row(a as usize) != grid.row(b as usize) {
-                    rows_different = true;
-                    break;
+        let cells: Vec<Vec<bool>> = s
+            .lines()
+            .map(|line| line.chars().map(|c| c == '#').collect())
+            .collect();
+        let width = cells[0].len();
+        let height = cells.len();
+        let mut rows = vec![vec![0; width.div_ceil(64)]; height];
+        let mut cols = vec![vec![0; height.div_ceil(64)]; width];
+        for (y, row) in cells.iter().enumerate() {
+            for (x, &rock) in row.iter().enumerate() {
+                if rock {
+                    rows[y][x / 64] |= 1 << (x % 64);
+                    cols[x][y / 64] |= 1 << (y % 64);
                 }
-                a -= 1;
-                b += 1;
-            }
-            if excluding != Some(&Reflection::Row(y)) && !rows_different {
-                return Some(Self::Row(y));
             }
         }
-
-        // Try every column
-        let width = grid.width();
-        for x in 0..width - 1 {
-            let mut cols_different = false;
-            let mut a = x as isize;
-            let mut b = x as isize + 1;
-            while a >= 0 && b < (width as isize) {
-                if grid.col(a as usize) != grid.col(b as usize) {
-                    cols_different = true;
-                    break;
-                }
-                a -= 1;
-                b += 1;
-            }
-            if excluding != Some(&Reflection::Col(x)) && !cols_different {
-                return Some(Self::Col(x));
-            }
-        }
-        None
+        Self { rows, cols }
     }
+}
 
-    fn of_q2(mut grid: Grid) -> Self {
-        // Answer cannot be this.
-        let answer_q1 = Self::of_q1(&grid, None).unwrap();
-        // Try smudging every cell.
-        for x in 0..grid.width() {
-            for y in 0..grid.height() {
-                // Smudge the grid at this cell.
-                grid.smudge(x, y);
+/// Finds the mirror line where exactly `k` cells would need to change for it to reflect perfectly.
+/// So k=0 finds a perfect reflection, and k=1 finds the reflection once a single smudge is cleaned.
+/// Rows are checked before columns.
+fn reflection_with_smudges(grid: &Grid, k: u32) -> Option<Reflection> {
+    mirror_line(&grid.rows, k)
+        .map(Reflection::Row)
+        .or_else(|| mirror_line(&grid.cols, k).map(Reflection::Col))
+}
 
-                // Was this smudge right?
-                match Self::of_q1(&grid, Some(&answer_q1)) {
-                    Some(answer) => {
-                        return answer;
-                    }
-                    _ => {
-                        // If not, unsmudge it.
-                        grid.smudge(x, y);
-                    }
-                }
+/// Finds i such that the lines reflected across the gap between line i and i+1 differ
+/// in exactly `k` cells in total.
+fn mirror_line(lines: &[Vec<u64>], k: u32) -> Option<usize> {
+    (0..lines.len().saturating_sub(1)).find(|&i| {
+        let mut differences = 0;
+        for (a, b) in lines[..=i].iter().rev().zip(&lines[i + 1..]) {
+            differences += a
+                .iter()
+                .zip(b)
+                .map(|(a, b)| (a ^ b).count_ones())
+                .sum::<u32>();
+            if differences > k {
+                return false;
             }
         }
-        panic!("Failed to find any new line of reflection in grid");
-    }
+        differences == k
+    })
+}
 
+impl Reflection {
     fn score(&self) -> usize {
         match self {
             Reflection::Col(x) => x + 1,
@@ -132,8 +100,46 @@ mod tests {
             (Reflection::Row(3), include_str!("../example2.txt")),
         ] {
             let map = Grid::parse(input);
-            let actual = Reflection::of_q1(&map, None).unwrap();
+            let actual = reflection_with_smudges(&map, 0).unwrap();
             assert_eq!(actual, expected);
         }
     }
+
+    #[test]
+    fn test_one_smudge() {
+        for (expected, input) in [
+            (Reflection::Row(2), include_str!("../example.txt")),
+            (Reflection::Row(0), include_str!("../example2.txt")),
+        ] {
+            let map = Grid::parse(input);
+            let actual = reflection_with_smudges(&map, 1).unwrap();
+            assert_eq!(actual, expected);
+        }
+    }
+
+    #[test]
+    fn test_many_smudges() {
+        // The rows differ in two cells. Columns 0 and 1 differ in one.
+        let map = Grid::parse("#...\n...#");
+        assert_eq!(reflection_with_smudges(&map, 0), None);
+        assert_eq!(reflection_with_smudges(&map, 1), Some(Reflection::Col(0)));
+        assert_eq!(reflection_with_smudges(&map, 2), Some(Reflection::Row(0)));
+        assert_eq!(reflection_with_smudges(&map, 3), None);
+    }
+
+    #[test]
+    fn test_wide_grid() {
+        // 70 columns, so each row needs two words. Rows 0 and 3 differ in the second word.
+        let row = |rocks: &[usize]| -> String {
+            (0..70)
+                .map(|x| if rocks.contains(&x) { '#' } else { '.' })
+                .collect()
+        };
+        let input = [row(&[0, 65]), row(&[3]), row(&[3]), row(&[0])].join("\n");
+        let map = Grid::parse(&input);
+        assert_eq!(reflection_with_smudges(&map, 1), Some(Reflection::Row(1)));
+        // Rows 2 and 3 differ in column 0 and column 3.
+        assert_eq!(reflection_with_smudges(&map, 2), Some(Reflection::Row(2)));
+        assert_eq!(reflection_with_smudges(&map, 3), Some(Reflection::Row(0)));
+    }
 }
@@ -1,9 +1,12 @@
 use rustc_hash::FxHashMap as HashMap;
+use std::ops::Range;
 
 fn main() {
     let input = Input::parse(include_str!("../input.txt"));
     let q1: u32 = input.simulate().map(Part::sum).sum();
-    println!("{q1}");
+    println!("Q1: {q1}");
+    let q2 = input.count_accepted();
+    println!("Q2: {q2}");
 }
 
 struct Input {
@@ -59,6 +62,42 @@ impl Input {
                 .expect("could not find workflow");
         }
     }
+
+    /// Counts every combination of ratings (each between 1 and 4000) that would be accepted.
+    fn count_accepted(&self) -> u64 {
+        let everything = PartRange {
+            x: 1..4001,
+            m: 1..4001,
+            a: 1..4001,
+            s: 1..4001,
+        };
+        let mut total = 0;
+        let mut queue = vec![(everything, "in")];
+        'ranges: while let Some((mut range, name)) = queue.pop() {
+            match is_terminal(name) {
+                Some(true) => {
+                    total += range.volume();
+                    continue;
+                }
+                Some(false) => continue,
+                None => {}
+            }
+            let wf = self.workflows.get(name).expect("could not find workflow");
+            for rule in &wf.rules {
+                let (matched, unmatched) = rule.split(range);
+                if let Some(matched) = matched {
+                    queue.push((matched, &rule.dst));
+                }
+                match unmatched {
+                    Some(unmatched) => range = unmatched,
+                    // Every part in this range was sent elsewhere, no need to check more rules.
+                    None => continue 'ranges,
+                }
+            }
+            queue.push((range, &wf.default));
+        }
+        total
+    }
 }
 
 fn is_terminal(s: &str) -> Option<bool> {
@@ -116,6 +155,35 @@ impl Part {
     }
 }
 
+/// Every combination of ratings where each component lies in its range.
+#[derive(Debug, Clone)]
+struct PartRange {
+    x: Range<u32>,
+    m: Range<u32>,
+    a: Range<u32>,
+    s: Range<u32>,
+}
+
+impl PartRange {
+    /// How many distinct parts are in this range.
+    fn volume(&self) -> u64 {
+        [&self.x, &self.m, &self.a, &self.s]
+            .into_iter()
+            .map(|r| r.len() as u64)
+            .product()
+    }
+
+    fn component_mut(&mut self, component: &str) -> &mut Range<u32> {
+        match component {
+            "x" => &mut self.x,
+            "m" => &mut self.m,
+            "a" => &mut self.a,
+            "s" => &mut self.s,
+            other => panic!("invalid component {other}"),
+        }
+    }
+}
+
 struct Workflow {
     name: String,
     rules: Vec<Rule>,
@@ -181,6 +249,27 @@ impl Rule {
             CmpType::Lesser => lhs < self.rhs,
         }
     }
+
+    /// Splits the range into (parts which match this rule, parts which don't).
+    /// Either side is None if no parts would end up there.
+    fn split(&self, range: PartRange) -> (Option<PartRange>, Option<PartRange>) {
+        let mut matched = range.clone();
+        let mut unmatched = range;
+        let m = matched.component_mut(&self.lhs);
+        let u = unmatched.component_mut(&self.lhs);
+        match self.cmp {
+            CmpType::Greater => {
+                m.start = m.start.max(self.rhs + 1);
+                u.end = u.end.min(self.rhs + 1);
+            }
+            CmpType::Lesser => {
+                m.end = m.end.min(self.rhs);
+                u.start = u.start.max(self.rhs);
+            }
+        }
+        let nonempty = |r: PartRange| (r.volume() > 0).then_some(r);
+        (nonempty(matched), nonempty(unmatched))
+    }
 }
 
 #[cfg(test)]
@@ -194,4 +283,10 @@ mod tests {
         let expected = vec![7540, 4623, 6951];
         assert_eq!(actual, expected);
     }
+
+    #[test]
+    fn test_q2() {
+        let input = Input::parse(include_str!("../example.txt"));
+        assert_eq!(input.count_accepted(), 167409079868000);
+    }
 }
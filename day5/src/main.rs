@@ -1,49 +1,80 @@
-use rayon::prelude::*;
+use std::collections::{HashMap, VecDeque};
+use std::ops::Range;
 
 type Id = usize;
 
 fn main() {
     let input = Input::parse(include_str!("../input.txt"));
+    let chain = input.chain("seed", "location").unwrap();
     // Q1
     let seeds = input.seeds.clone();
-    println!("Q1: {}", lowest_location(seeds, &input));
+    println!("Q1: {}", lowest_location(seeds, &chain));
 
     // Q2
-    let seeds: Vec<_> = input
+    let q2 = lowest_location_of_ranges(input.seed_ranges(), &chain);
+    println!("Q2: {q2}");
+
+    // Collapsing all the maps into one should give the same answer.
+    let seed_to_location = input.composed("seed", "location").unwrap();
+    let lowest = input
         .seed_ranges()
-        .into_par_iter()
-        .flat_map(|(start, len)| {
-            vec![0; len]
-                .into_iter()
-                .enumerate()
-                .map(|(i, _)| i + start)
-                .collect::<Vec<_>>()
-        })
-        .collect();
-    println!("Brute-force checking each of {} seeds", seeds.len());
-    println!("Q2: {}", lowest_location(seeds, &input));
+        .into_iter()
+        .filter_map(|range| seed_to_location.lowest_output_in(range))
+        .min();
+    assert_eq!(lowest, Some(q2));
+    println!(
+        "Location {q2} is reached from seed(s) {:?}",
+        seed_to_location.preimage(q2)
+    );
 }
 
-fn lowest_location(mut seeds: Vec<Id>, input: &Input) -> Id {
-    for map_id in 0..7 {
-        seeds = seeds
-            .iter()
-            .map(|seed| input.maps[map_id].do_map(*seed))
-            .collect();
+fn lowest_location(mut seeds: Vec<Id>, chain: &[&Map]) -> Id {
+    for map in chain {
+        seeds = seeds.iter().map(|seed| map.do_map(*seed)).collect();
     }
     *seeds.iter().min().unwrap()
 }
 
+/// Like `lowest_location` but maps whole ranges of seeds at once, instead of each seed individually.
+fn lowest_location_of_ranges(mut ranges: Vec<Range<Id>>, chain: &[&Map]) -> Id {
+    for map in chain {
+        ranges = ranges
+            .into_iter()
+            .flat_map(|range| map.map_range(range))
+            .collect();
+    }
+    ranges.iter().map(|range| range.start).min().unwrap()
+}
+
+#[derive(Debug, PartialEq, Eq)]
+enum CategoryError {
+    /// No map converts to or from this category.
+    Unknown(String),
+    /// Both categories exist, but no sequence of maps converts from one to the other.
+    Disconnected { from: String, to: String },
+}
+
+impl std::fmt::Display for CategoryError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Unknown(category) => write!(f, "unknown category {category}"),
+            Self::Disconnected { from, to } => write!(f, "no maps convert {from} to {to}"),
+        }
+    }
+}
+
 #[derive(Debug)]
 struct Input {
     seeds: Vec<Id>,
-    maps: Vec<Map>,
+    /// Source category -> destination category -> the map between them.
+    maps: HashMap<String, HashMap<String, Map>>,
 }
 
 impl Input {
-    fn seed_ranges(&self) -> Vec<(Id, Id)> {
-        (0..self.seeds.len() / 2)
-            .map(|i| (self.seeds[2 * i], self.seeds[2 * i + 1]))
+    fn seed_ranges(&self) -> Vec<Range<Id>> {
+        self.seeds
+            .chunks_exact(2)
+            .map(|pair| pair[0]..pair[0] + pair[1])
             .collect()
     }
 
@@ -58,47 +89,212 @@ impl Input {
             .split_ascii_whitespace()
             .map(|num| num.parse::<Id>().unwrap())
             .collect();
-        lines.next();
 
-        let mut maps = Vec::new();
-        for _ in 0..7 {
-            lines.next().unwrap();
-            maps.push(Map::parse(&mut lines));
+        let mut maps: HashMap<String, HashMap<String, Map>> = HashMap::new();
+        while let Some(header) = lines.next() {
+            if header.trim().is_empty() {
+                continue;
+            }
+            let (src, dst) = header
+                .strip_suffix(" map:")
+                .and_then(|categories| categories.split_once("-to-"))
+                .unwrap_or_else(|| panic!("invalid map header {header}"));
+            maps.entry(src.to_owned())
+                .or_default()
+                .insert(dst.to_owned(), Map::parse(&mut lines));
         }
 
         Input { seeds, maps }
     }
+
+    fn has_category(&self, category: &str) -> bool {
+        self.maps.contains_key(category)
+            || self.maps.values().any(|dsts| dsts.contains_key(category))
+    }
+
+    /// The shortest sequence of maps which converts IDs in category `from` to category `to`.
+    fn chain(&self, from: &str, to: &str) -> Result<Vec<&Map>, CategoryError> {
+        for category in [from, to] {
+            if !self.has_category(category) {
+                return Err(CategoryError::Unknown(category.to_owned()));
+            }
+        }
+        // Breadth-first search, remembering how we reached each category.
+        let mut came_from: HashMap<&str, (&str, &Map)> = HashMap::new();
+        let mut queue = VecDeque::from([from]);
+        while let Some(curr) = queue.pop_front() {
+            if curr == to {
+                let mut chain = Vec::new();
+                let mut curr = to;
+                while let Some((prev, map)) = came_from.get(curr) {
+                    chain.push(*map);
+                    curr = prev;
+                }
+                chain.reverse();
+                return Ok(chain);
+            }
+            for (next, map) in self.maps.get(curr).into_iter().flatten() {
+                if next != from && !came_from.contains_key(next.as_str()) {
+                    came_from.insert(next, (curr, map));
+                    queue.push_back(next);
+                }
+            }
+        }
+        Err(CategoryError::Disconnected {
+            from: from.to_owned(),
+            to: to.to_owned(),
+        })
+    }
+
+    /// Collapses every map between the two categories into a single map.
+    fn composed(&self, from: &str, to: &str) -> Result<Map, CategoryError> {
+        Ok(self
+            .chain(from, to)?
+            .into_iter()
+            .fold(Map::new(Vec::new()), |composed, map| composed.then(map)))
+    }
 }
 
+/// Rows are sorted by source and never overlap. Any ID not covered by a row maps to itself.
 #[derive(Debug)]
 struct Map {
     rows: Vec<Row>,
 }
 
 impl Map {
+    fn new(mut rows: Vec<Row>) -> Self {
+        rows.sort_by_key(|row| row.source_range_start);
+        Self { rows }
+    }
+
     fn do_map(&self, input: Id) -> Id {
+        let i = self
+            .rows
+            .partition_point(|row| row.source_range().end <= input);
+        match self.rows.get(i) {
+            Some(row) if row.contains(input) => row.do_map(input),
+            _ => input,
+        }
+    }
+
+    /// Every ID which this map sends to `output`. Empty if nothing maps there.
+    fn preimage(&self, output: Id) -> Vec<Id> {
+        let mut inputs: Vec<_> = self
+            .rows
+            .iter()
+            .filter(|row| row.destination_range().contains(&output))
+            .map(|row| row.source_range_start + (output - row.destination_range_start))
+            .collect();
+        // If no row covers `output` as a source, it maps to itself.
+        if !self.rows.iter().any(|row| row.contains(output)) {
+            inputs.push(output);
+        }
+        inputs.sort();
+        inputs
+    }
+
+    /// The lowest ID that anything in `input` maps to.
+    fn lowest_output_in(&self, input: Range<Id>) -> Option<Id> {
+        self.map_range(input)
+            .into_iter()
+            .map(|range| range.start)
+            .min()
+    }
+
+    /// Every row, plus identity rows filling the gaps between them, so that the
+    /// returned rows cover every ID (up to Id::MAX).
+    fn pieces(&self) -> Vec<Row> {
+        let mut pieces = Vec::with_capacity(self.rows.len() * 2 + 1);
+        let mut next_uncovered = 0;
         for row in &self.rows {
-            if row.contains(input) {
-                return row.destination_range_start + (input - row.source_range_start);
+            if next_uncovered < row.source_range_start {
+                pieces.push(Row::identity(next_uncovered..row.source_range_start));
             }
+            pieces.push(*row);
+            next_uncovered = row.source_range().end;
         }
-        input
+        pieces.push(Row::identity(next_uncovered..Id::MAX));
+        pieces
     }
+
+    /// Returns a map equivalent to applying `self` and then `next`.
+    /// The result is normalised: identity rows are dropped and adjacent rows
+    /// with the same offset are merged.
+    fn then(&self, next: &Map) -> Map {
+        let next_pieces = next.pieces();
+        let mut rows: Vec<Row> = Vec::new();
+        for piece in self.pieces() {
+            let dst = piece.destination_range();
+            let first = next_pieces.partition_point(|p| p.source_range().end <= dst.start);
+            for next_piece in &next_pieces[first..] {
+                let next_src = next_piece.source_range();
+                if next_src.start >= dst.end {
+                    break;
+                }
+                let overlap = dst.start.max(next_src.start)..dst.end.min(next_src.end);
+                let row = Row {
+                    source_range_start: piece.source_range_start
+                        + (overlap.start - piece.destination_range_start),
+                    destination_range_start: next_piece.do_map(overlap.start),
+                    range_length: overlap.len(),
+                };
+                if row.is_identity() {
+                    continue;
+                }
+                match rows.last_mut() {
+                    Some(prev)
+                        if prev.source_range().end == row.source_range_start
+                            && prev.destination_range().end == row.destination_range_start =>
+                    {
+                        prev.range_length += row.range_length;
+                    }
+                    _ => rows.push(row),
+                }
+            }
+        }
+        // Pieces were visited in source order, so the rows are already sorted.
+        Map { rows }
+    }
+
+    /// Maps every ID in the range. The output ranges are split wherever the input range
+    /// crosses a row boundary. Parts of the input not covered by any row map to themselves.
+    fn map_range(&self, input: Range<Id>) -> Vec<Range<Id>> {
+        let mut unmapped = vec![input];
+        let mut mapped = Vec::new();
+        for row in &self.rows {
+            let src = row.source_range();
+            let mut still_unmapped = Vec::new();
+            for range in unmapped {
+                let before = range.start..range.end.min(src.start);
+                let overlap = range.start.max(src.start)..range.end.min(src.end);
+                let after = range.start.max(src.end)..range.end;
+                if !overlap.is_empty() {
+                    let offset = row.destination_range_start;
+                    mapped.push(
+                        offset + (overlap.start - src.start)..offset + (overlap.end - src.start),
+                    );
+                }
+                still_unmapped.extend([before, after].into_iter().filter(|r| !r.is_empty()));
+            }
+            unmapped = still_unmapped;
+        }
+        mapped.extend(unmapped);
+        mapped
+    }
+
     fn parse<'a, I>(lines: &mut I) -> Self
     where
         I: Iterator<Item = &'a str>,
     {
-        let mut rows = Vec::new();
-        let mut line = lines.next().unwrap();
-        while !line.trim().is_empty() {
-            rows.push(Row::parse(line));
-            line = lines.next().unwrap();
-        }
-        Self { rows }
+        let rows = lines
+            .take_while(|line| !line.trim().is_empty())
+            .map(Row::parse)
+            .collect();
+        Self::new(rows)
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 struct Row {
     destination_range_start: Id,
     source_range_start: Id,
@@ -107,8 +303,34 @@ struct Row {
 
 impl Row {
     fn contains(&self, input: Id) -> bool {
-        (self.source_range_start..self.source_range_start + self.range_length).contains(&input)
+        self.source_range().contains(&input)
+    }
+
+    fn source_range(&self) -> Range<Id> {
+        self.source_range_start..self.source_range_start + self.range_length
+    }
+
+    fn destination_range(&self) -> Range<Id> {
+        self.destination_range_start..self.destination_range_start + self.range_length
+    }
+
+    /// Assumes the input is in this row's source range.
+    fn do_map(&self, input: Id) -> Id {
+        self.destination_range_start + (input - self.source_range_start)
+    }
+
+    fn identity(range: Range<Id>) -> Self {
+        Self {
+            destination_range_start: range.start,
+            source_range_start: range.start,
+            range_length: range.len(),
+        }
+    }
+
+    fn is_identity(&self) -> bool {
+        self.source_range_start == self.destination_range_start
     }
+
     fn parse(s: &str) -> Self {
         let v = s
             .split_ascii_whitespace()
@@ -123,3 +345,98 @@ impl Row {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_q1() {
+        let input = Input::parse(include_str!("../example.txt"));
+        let chain = input.chain("seed", "location").unwrap();
+        assert_eq!(lowest_location(input.seeds.clone(), &chain), 35);
+    }
+
+    #[test]
+    fn test_q2_ranges_match_brute_force() {
+        let input = Input::parse(include_str!("../example.txt"));
+        let chain = input.chain("seed", "location").unwrap();
+        let seeds: Vec<_> = input.seed_ranges().into_iter().flatten().collect();
+        let expected = lowest_location(seeds, &chain);
+        assert_eq!(expected, 46);
+        assert_eq!(
+            lowest_location_of_ranges(input.seed_ranges(), &chain),
+            expected
+        );
+    }
+
+    #[test]
+    fn test_map_range_splits_at_row_boundaries() {
+        let map = Map::new(vec![Row::parse("50 98 2"), Row::parse("52 50 48")]);
+        let mut actual = map.map_range(40..100);
+        actual.sort_by_key(|r| r.start);
+        assert_eq!(actual, vec![40..50, 50..52, 52..100]);
+    }
+
+    #[test]
+    fn test_composed_map_matches_chain() {
+        let input = Input::parse(include_str!("../example.txt"));
+        let chain = input.chain("seed", "location").unwrap();
+        let composed = input.composed("seed", "location").unwrap();
+        // Rows must be sorted, non-overlapping and never identity.
+        for pair in composed.rows.windows(2) {
+            assert!(pair[0].source_range().end <= pair[1].source_range_start);
+        }
+        assert!(composed.rows.iter().all(|row| !row.is_identity()));
+        for seed in 0..200 {
+            let expected = chain.iter().fold(seed, |id, map| map.do_map(id));
+            assert_eq!(composed.do_map(seed), expected, "seed {seed}");
+            assert!(composed.preimage(expected).contains(&seed));
+        }
+        assert_eq!(composed.preimage(46), vec![82]);
+        assert_eq!(composed.lowest_output_in(79..93), Some(46));
+    }
+
+    #[test]
+    fn test_partial_chain() {
+        let input = Input::parse(include_str!("../example.txt"));
+        assert_eq!(input.chain("seed", "location").unwrap().len(), 7);
+        let seed_to_humidity = input.composed("seed", "humidity").unwrap();
+        // From the puzzle's worked example.
+        assert_eq!(seed_to_humidity.do_map(79), 78);
+        assert_eq!(seed_to_humidity.do_map(14), 43);
+        assert_eq!(input.composed("light", "light").unwrap().do_map(5), 5);
+    }
+
+    #[test]
+    fn test_chain_errors() {
+        let input = Input::parse(include_str!("../example.txt"));
+        assert_eq!(
+            input.chain("seed", "banana").unwrap_err(),
+            CategoryError::Unknown("banana".to_owned())
+        );
+        assert_eq!(
+            input.chain("location", "seed").unwrap_err(),
+            CategoryError::Disconnected {
+                from: "location".to_owned(),
+                to: "seed".to_owned()
+            }
+        );
+    }
+
+    #[test]
+    fn test_fewer_stages() {
+        let input = Input::parse(
+            "seeds: 1 2
+
+seed-to-soil map:
+10 0 5
+
+soil-to-location map:
+0 10 2",
+        );
+        let chain = input.chain("seed", "location").unwrap();
+        assert_eq!(chain.len(), 2);
+        assert_eq!(lowest_location(input.seeds.clone(), &chain), 1);
+    }
+}
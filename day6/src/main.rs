@@ -1,8 +1,10 @@
-use rayon::prelude::*;
+use std::ops::RangeInclusive;
+
+type Time = u128;
 
 fn main() {
     let input = include_str!("../input.txt");
-    let q1: usize = parse_q1(input).iter().map(Race::ways_to_beat).product();
+    let q1: Time = parse_q1(input).iter().map(Race::ways_to_beat).product();
     println!("Q1: {q1}");
     let q2 = parse_q2(input).ways_to_beat();
     println!("Q2: {q2}");
@@ -10,25 +12,79 @@ fn main() {
 
 #[derive(Debug)]
 struct Race {
-    duration: u64,
-    best_distance: u64,
+    duration: Time,
+    best_distance: Time,
 }
 
 impl Race {
-    fn ways_to_beat(&self) -> usize {
-        (1u64..self.duration)
-            .into_par_iter()
-            .filter(|acceleration| {
-                let time_remaining = self.duration - acceleration;
-                let distance_travelled = acceleration * time_remaining;
-                distance_travelled > self.best_distance
-            })
-            .count()
+    fn ways_to_beat(&self) -> Time {
+        self.winning_holds()
+            .map_or(0, |holds| holds.end() - holds.start() + 1)
+    }
+
+    /// Distance travelled if the button is held for this long.
+    /// None if the distance is too big for a `Time`.
+    fn distance_travelled(&self, hold: Time) -> Option<Time> {
+        hold.checked_mul(self.duration - hold)
+    }
+
+    fn beats_record(&self, hold: Time) -> bool {
+        self.distance_travelled(hold)
+            .is_none_or(|distance| distance > self.best_distance)
+    }
+
+    /// The hold time which travels furthest.
+    fn optimal_hold(&self) -> Time {
+        self.duration / 2
+    }
+
+    /// Every hold time which beats the record, or None if the record can't be beaten.
+    /// Distance is symmetric around the optimal hold, so the winning holds are too.
+    fn winning_holds(&self) -> Option<RangeInclusive<Time>> {
+        let best = self.optimal_hold();
+        if !self.beats_record(best) {
+            return None;
+        }
+        let first = self.first_winning_hold(best);
+        Some(first..=self.duration - first)
+    }
+
+    /// Smallest hold time that beats the record. Assumes `best` beats it.
+    fn first_winning_hold(&self, best: Time) -> Time {
+        // Solve hold * (duration - hold) > best_distance, i.e. the lower root of
+        // hold^2 - duration * hold + best_distance = 0.
+        let discriminant = self
+            .duration
+            .checked_mul(self.duration)
+            .zip(self.best_distance.checked_mul(4))
+            .map(|(t2, d4)| t2 - d4);
+        let Some(discriminant) = discriminant else {
+            // Too big to square, so binary search for the boundary instead.
+            let (mut lo, mut hi) = (0, best);
+            while lo < hi {
+                let mid = lo + (hi - lo) / 2;
+                if self.beats_record(mid) {
+                    hi = mid;
+                } else {
+                    lo = mid + 1;
+                }
+            }
+            return lo;
+        };
+        // The integer square root rounds down, so the estimate can be off by one either way.
+        let mut hold = (self.duration - discriminant.isqrt()) / 2;
+        while hold > 0 && self.beats_record(hold - 1) {
+            hold -= 1;
+        }
+        while !self.beats_record(hold) {
+            hold += 1;
+        }
+        hold
     }
 }
 
 fn parse_q1(s: &str) -> Vec<Race> {
-    fn parse_line(s: &str) -> Vec<u64> {
+    fn parse_line(s: &str) -> Vec<Time> {
         s.split_once(':')
             .unwrap()
             .1
@@ -54,7 +110,7 @@ fn parse_q2(s: &str) -> Race {
     let mut lines = s.lines();
     let time = lines.next().unwrap().split_once(':').unwrap().1.trim();
     let dist = lines.next().unwrap().split_once(':').unwrap().1.trim();
-    fn join_bad_kerning(s: &str) -> u64 {
+    fn join_bad_kerning(s: &str) -> Time {
         s.chars()
             .filter(char::is_ascii_digit)
             .collect::<String>()
@@ -66,3 +122,55 @@ fn parse_q2(s: &str) -> Race {
         duration: join_bad_kerning(time),
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_example() {
+        let input = include_str!("../example.txt");
+        let races = parse_q1(input);
+        let ways: Vec<_> = races.iter().map(Race::ways_to_beat).collect();
+        assert_eq!(ways, vec![4, 8, 9]);
+        assert_eq!(races[0].winning_holds(), Some(2..=5));
+        assert_eq!(parse_q2(input).ways_to_beat(), 71503);
+    }
+
+    #[test]
+    fn test_matches_brute_force() {
+        for duration in 0..60 {
+            for best_distance in 0..(duration * duration / 4 + 2) {
+                let race = Race {
+                    duration,
+                    best_distance,
+                };
+                let winners: Vec<_> = (0..=duration)
+                    .filter(|hold| hold * (duration - hold) > best_distance)
+                    .collect();
+                let expected = winners
+                    .first()
+                    .map(|first| *first..=*winners.last().unwrap());
+                assert_eq!(race.winning_holds(), expected, "{race:?}");
+                assert_eq!(race.ways_to_beat(), winners.len() as Time, "{race:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn test_huge_durations() {
+        let race = Race {
+            duration: Time::MAX,
+            best_distance: 0,
+        };
+        assert_eq!(race.winning_holds(), Some(1..=Time::MAX - 1));
+        assert_eq!(race.optimal_hold(), Time::MAX / 2);
+
+        let race = Race {
+            duration: 1 << 64,
+            best_distance: (1 << 126) - 1,
+        };
+        // Only the exact midpoint travels far enough.
+        assert_eq!(race.winning_holds(), Some(1 << 63..=1 << 63));
+    }
+}
@@ -1,13 +1,13 @@
-use std::{cmp::Ordering, collections::HashMap};
+use std::collections::HashMap;
 
 mod parse;
 
 fn main() {
     let data = Data::parse(include_str!("../input.txt"));
-    let q1 = total_scores(&data, Q::Q1);
+    let q1 = total_scores(&data, &Standard);
     assert_eq!(250957639, q1);
     println!("Q1: {q1}");
-    let q2 = total_scores(&data, Q::Q2);
+    let q2 = total_scores(&data, &JokersWild);
     assert_eq!(251515496, q2);
     println!("Q2: {q2}");
 }
@@ -19,7 +19,7 @@ struct Row {
 }
 
 #[derive(Eq, PartialEq, Clone, Debug)]
-struct Hand([Card; 5]);
+struct Hand(Vec<Card>);
 
 #[derive(Eq, PartialEq, Clone, Copy, Debug, Hash)]
 enum Card {
@@ -52,25 +52,9 @@ impl Card {
             Card::Number(n) => 9 - *n + 6,
         }
     }
-    fn list_q2() -> [Self; 12] {
-        [
-            Card::A,
-            Card::K,
-            Card::Q,
-            Card::T,
-            Card::Number(9),
-            Card::Number(8),
-            Card::Number(7),
-            Card::Number(6),
-            Card::Number(5),
-            Card::Number(4),
-            Card::Number(3),
-            Card::Number(2),
-        ]
-    }
 }
 
-#[derive(Debug, Eq, PartialEq, Ord, PartialOrd)]
+#[derive(Debug, Eq, PartialEq, Ord, PartialOrd, Clone, Copy)]
 enum HandType {
     /// Five of a kind, where all five cards have the same label: AAAAA
     FiveOfAKind,
@@ -89,14 +73,16 @@ enum HandType {
 }
 
 impl HandType {
+    /// Takes how many times each card appears, sorted from least to most frequent.
+    /// Only the two most frequent cards matter, so this works for hands of any size.
     fn from_card_frequencies(frequencies: Vec<usize>) -> Self {
         match frequencies.as_slice() {
-            [5] => Self::FiveOfAKind,
-            [1, 4] => Self::FourOfAKind,
-            [2, 3] => Self::FullHouse,
-            vals if vals.contains(&3) => Self::ThreeOfAKind,
-            [1, 2, 2] => HandType::TwoPair,
-            vals if vals.contains(&2) => Self::OnePair,
+            [.., most] if *most >= 5 => Self::FiveOfAKind,
+            [.., 4] => Self::FourOfAKind,
+            [.., second, 3] if *second >= 2 => Self::FullHouse,
+            [.., 3] => Self::ThreeOfAKind,
+            [.., 2, 2] => Self::TwoPair,
+            [.., 2] => Self::OnePair,
             _ => Self::HighCard,
         }
     }
@@ -104,106 +90,108 @@ impl HandType {
 
 impl Hand {
     fn classify_q1(&self) -> HandType {
-        let mut freqs = HashMap::new();
-        for ch in self.0 {
-            *freqs.entry(ch).or_insert(0) += 1;
-        }
-        let mut vals: Vec<_> = freqs.values().copied().collect();
-        vals.sort();
-        HandType::from_card_frequencies(vals)
+        HandType::from_card_frequencies(self.card_frequencies(&[]))
     }
 
     fn classify_q2(&self) -> HandType {
+        HandType::from_card_frequencies(self.card_frequencies(&[Card::J]))
+    }
+
+    /// How many times each card appears, sorted from least to most frequent,
+    /// after every wildcard has been turned into whichever card makes the best hand.
+    ///
+    /// One hand beats another if its most frequent card is more frequent, or if they tie,
+    /// its second most frequent card is more frequent, and so on. Adding every wildcard to
+    /// the most frequent card makes the first comparison as big as it can possibly be, and
+    /// any other assignment which ties it must have done the same thing, so this is optimal.
+    fn card_frequencies(&self, wildcards: &[Card]) -> Vec<usize> {
         let mut freqs = HashMap::new();
-        let mut jokers = 0;
-        for ch in self.0 {
-            if ch == Card::J {
-                jokers += 1
+        let mut wild = 0;
+        for card in &self.0 {
+            if wildcards.contains(card) {
+                wild += 1;
             } else {
-                *freqs.entry(ch).or_insert(0) += 1;
-            }
-        }
-        if jokers == 5 {
-            return HandType::FiveOfAKind;
-        }
-        if jokers > 0 {
-            // If there's already a card with multiples, convert the jokers to that card.
-            let (most_freq_character, frequency) = freqs
-                .iter()
-                .max_by(|this, other| this.1.cmp(other.1))
-                .unwrap()
-                .to_owned();
-            if frequency > &1 {
-                freqs.insert(*most_freq_character, frequency + jokers);
-            } else {
-                // Make the joker the highest-value card I guess
-                for ch in Card::list_q2() {
-                    if let Some(x) = freqs.get(&ch) {
-                        freqs.insert(ch, x + jokers);
-                        break;
-                    }
-                }
+                *freqs.entry(*card).or_insert(0) += 1;
             }
         }
-        let mut vals: Vec<_> = freqs.values().copied().collect();
+        let mut vals: Vec<_> = freqs.into_values().collect();
         vals.sort();
-        HandType::from_card_frequencies(vals)
+        match vals.last_mut() {
+            Some(most) => *most += wild,
+            None if wild > 0 => vals.push(wild),
+            None => {}
+        }
+        vals
     }
 }
 
-#[derive(Eq, PartialEq, Clone, Copy, Debug)]
-enum Q {
-    Q1,
-    Q2,
-}
-
-#[derive(Clone, Debug)]
-struct RowForQuestion<'a> {
-    row: &'a Row,
-    question: Q,
-}
+/// The rules for ranking hands. Lower ranks beat higher ranks.
+trait Ruleset {
+    /// Rank the hand by its type (e.g. five of a kind beats two pair).
+    fn hand_rank(&self, hand: &Hand) -> u32;
+    /// Rank a single card. Used to break ties between hands with the same type.
+    /// Must be less than 256.
+    fn card_rank(&self, card: Card) -> u32;
 
-impl<'a> PartialEq for RowForQuestion<'a> {
-    fn eq(&self, other: &Self) -> bool {
-        self.row.hand == other.row.hand
-    }
-}
-impl<'a> Eq for RowForQuestion<'a> {}
-
-impl<'a> RowForQuestion<'a> {
-    fn hand_type(&self) -> HandType {
-        match self.question {
-            Q::Q1 => self.row.hand.classify_q1(),
-            Q::Q2 => self.row.hand.classify_q2(),
+    /// Packs the hand rank and every card's rank into one integer, so that comparing two
+    /// hands' keys compares the hands. The hand rank goes in the top 32 bits, then each card
+    /// takes 9 bits, storing its rank plus one so that a hand sorts before any longer hand
+    /// it's the start of. Returns None if the hand has more than `MAX_PACKED_CARDS` cards.
+    fn packed_sort_key(&self, hand: &Hand) -> Option<u128> {
+        if hand.0.len() > MAX_PACKED_CARDS {
+            return None;
         }
+        let cards = hand.0.iter().fold(0, |key, card| {
+            (key << CARD_BITS) | (u128::from(checked_card_rank(self, *card)) + 1)
+        });
+        let padding = CARD_BITS * (MAX_PACKED_CARDS - hand.0.len());
+        Some((u128::from(self.hand_rank(hand)) << 96) | (cards << padding))
     }
-    fn card_values(&self) -> Vec<u32> {
-        self.row
-            .hand
+
+    /// The hand rank followed by every card's rank, for hands too long to pack.
+    /// Orders hands the same way as `packed_sort_key`.
+    fn sort_key(&self, hand: &Hand) -> (u32, Vec<u8>) {
+        let card_ranks = hand
             .0
             .iter()
-            .map(|card| match self.question {
-                Q::Q1 => card.eval_q1(),
-                Q::Q2 => card.eval_q2(),
-            })
-            .collect()
+            .map(|card| checked_card_rank(self, *card))
+            .collect();
+        (self.hand_rank(hand), card_ranks)
     }
 }
 
-#[derive(Eq, PartialEq, Clone, Debug)]
-struct Q2<'a>(&'a Hand);
+/// Bits used for each card in `Ruleset::packed_sort_key`.
+const CARD_BITS: usize = 9;
+/// The most cards that fit below the hand rank in `Ruleset::packed_sort_key`.
+const MAX_PACKED_CARDS: usize = 96 / CARD_BITS;
 
-impl<'a> PartialOrd for RowForQuestion<'a> {
-    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
-        Some(self.cmp(other))
+fn checked_card_rank(ruleset: &(impl Ruleset + ?Sized), card: Card) -> u8 {
+    let card_rank = ruleset.card_rank(card);
+    u8::try_from(card_rank)
+        .unwrap_or_else(|_| panic!("card rank {card_rank} doesn't fit in 8 bits"))
+}
+
+/// The rules from part 1.
+struct Standard;
+
+impl Ruleset for Standard {
+    fn hand_rank(&self, hand: &Hand) -> u32 {
+        hand.classify_q1() as u32
+    }
+    fn card_rank(&self, card: Card) -> u32 {
+        card.eval_q1()
     }
 }
 
-impl<'a> Ord for RowForQuestion<'a> {
-    fn cmp(&self, other: &Self) -> Ordering {
-        self.hand_type()
-            .cmp(&other.hand_type())
-            .then_with(|| self.card_values().cmp(&other.card_values()))
+/// The rules from part 2: J is a joker, which can stand in for any card but is the weakest card.
+struct JokersWild;
+
+impl Ruleset for JokersWild {
+    fn hand_rank(&self, hand: &Hand) -> u32 {
+        hand.classify_q2() as u32
+    }
+    fn card_rank(&self, card: Card) -> u32 {
+        card.eval_q2()
     }
 }
 
@@ -211,19 +199,32 @@ struct Data {
     rows: Vec<Row>,
 }
 
-fn total_scores(data: &Data, question: Q) -> usize {
-    // Sort all rows, highest-ranked hands first.
+fn total_scores(data: &Data, ruleset: &impl Ruleset) -> usize {
+    // Hands usually fit in one integer, which is cheaper to sort than a Vec of card ranks.
+    if data
+        .rows
+        .iter()
+        .all(|row| row.hand.0.len() <= MAX_PACKED_CARDS)
+    {
+        score_sorted(data, |hand| ruleset.packed_sort_key(hand).unwrap())
+    } else {
+        score_sorted(data, |hand| ruleset.sort_key(hand))
+    }
+}
+
+fn score_sorted<K: Ord>(data: &Data, sort_key: impl Fn(&Hand) -> K) -> usize {
+    // Score each hand once, then sort all rows, highest-ranked hands first.
     let mut rows: Vec<_> = data
         .rows
         .iter()
-        .map(|row| RowForQuestion { row, question })
+        .map(|row| (sort_key(&row.hand), row.bid))
         .collect();
-    rows.sort();
+    rows.sort_unstable();
     // Calculate the total score by summing each row's bid * rank.
     let n = rows.len();
     rows.iter()
         .enumerate()
-        .map(|(i, row)| (n - i) * row.row.bid)
+        .map(|(i, (_key, bid))| (n - i) * bid)
         .sum()
 }
 
@@ -235,7 +236,7 @@ mod tests {
     fn test_q1() {
         let example = include_str!("../example.txt");
         let data = Data::parse(example);
-        let actual = total_scores(&data, Q::Q1);
+        let actual = total_scores(&data, &Standard);
         let expected = 6440;
         assert_eq!(actual, expected);
     }
@@ -244,11 +245,93 @@ mod tests {
     fn test_q2() {
         let example = include_str!("../example.txt");
         let data = Data::parse(example);
-        let actual = total_scores(&data, Q::Q2);
+        let actual = total_scores(&data, &JokersWild);
         let expected = 5905;
         assert_eq!(actual, expected);
     }
 
+    /// Compares hands by type, then card by card, without any sort key.
+    fn compare_hands(ruleset: &dyn Ruleset, a: &Hand, b: &Hand) -> std::cmp::Ordering {
+        let card_ranks = |hand: &Hand| -> Vec<u32> {
+            hand.0.iter().map(|card| ruleset.card_rank(*card)).collect()
+        };
+        ruleset
+            .hand_rank(a)
+            .cmp(&ruleset.hand_rank(b))
+            .then_with(|| card_ranks(a).cmp(&card_ranks(b)))
+    }
+
+    #[test]
+    fn test_sort_key_orders_like_hands() {
+        let mut hands: Vec<Hand> = Data::parse(include_str!("../example.txt"))
+            .rows
+            .into_iter()
+            .map(|row| row.hand)
+            .collect();
+        // Hands of different lengths, including ones which start the same way,
+        // and ones as long as a packed key allows.
+        for hand in [
+            "32T3",
+            "32T3K2",
+            "AAAA",
+            "AAAA2",
+            "22",
+            "2",
+            "AKQJT98765",
+            "AKQJT98764",
+        ] {
+            hands.push(Hand(hand.chars().map(Card::parse).collect()));
+        }
+        for a in &hands {
+            for b in &hands {
+                for ruleset in [&Standard as &dyn Ruleset, &JokersWild, &AcesWildDeucesHigh] {
+                    let expected = compare_hands(ruleset, a, b);
+                    let packed = ruleset.packed_sort_key(a).cmp(&ruleset.packed_sort_key(b));
+                    assert_eq!(packed, expected, "{a:?} vs {b:?}");
+                    let unpacked = ruleset.sort_key(a).cmp(&ruleset.sort_key(b));
+                    assert_eq!(unpacked, expected, "{a:?} vs {b:?}");
+                }
+            }
+        }
+        let too_long = Hand("AKQJT98765A".chars().map(Card::parse).collect());
+        assert_eq!(Standard.packed_sort_key(&too_long), None);
+    }
+
+    /// Aces are wild and deuces beat everything else, to check other rulesets can be plugged in.
+    struct AcesWildDeucesHigh;
+
+    impl Ruleset for AcesWildDeucesHigh {
+        fn hand_rank(&self, hand: &Hand) -> u32 {
+            HandType::from_card_frequencies(hand.card_frequencies(&[Card::A])) as u32
+        }
+        fn card_rank(&self, card: Card) -> u32 {
+            match card {
+                Card::Number(2) => 0,
+                Card::A => 99,
+                other => other.eval_q1(),
+            }
+        }
+    }
+
+    #[test]
+    fn test_long_hands() {
+        // With 8 cards, the hand type still matters more than any card.
+        let data = Data::parse("22222333 1\nAKQJT987 10");
+        assert_eq!(total_scores(&data, &Standard), 2 + 10);
+        // Too long to pack into an integer key.
+        let data = Data::parse("22222333333 1\nAKQJT987654 10");
+        assert_eq!(total_scores(&data, &Standard), 2 + 10);
+    }
+
+    #[test]
+    fn test_custom_ruleset() {
+        let data = Data::parse("AA223 1\n22A34 10\n33345 100");
+        // Both aces join the deuces, so AA223 is four of a kind and ranks highest.
+        // 22A34 and 33345 are both three of a kind, but 2s are high so 22A34 wins the tie.
+        let actual = total_scores(&data, &AcesWildDeucesHigh);
+        assert_eq!(actual, 3 + 2 * 10 + 100);
+    }
+
     #[test]
     fn hand_types() {
         for (row, expected) in [
@@ -263,19 +346,12 @@ mod tests {
             ("32T3K", HandType::OnePair),
         ] {
             assert_eq!(
-                Hand(
-                    row.chars()
-                        .map(Card::parse)
-                        .collect::<Vec<_>>()
-                        .try_into()
-                        .unwrap()
-                )
-                .classify_q1(),
+                Hand(row.chars().map(Card::parse).collect()).classify_q1(),
                 expected,
             );
         }
         assert_eq!(
-            Hand([
+            Hand(vec![
                 Card::T,
                 Card::Number(5),
                 Card::Number(5),
@@ -286,12 +362,138 @@ mod tests {
             HandType::FourOfAKind
         );
         assert_eq!(
-            Hand([Card::Q, Card::Q, Card::Q, Card::J, Card::A,]).classify_q2(),
+            Hand(vec![Card::Q, Card::Q, Card::Q, Card::J, Card::A,]).classify_q2(),
             HandType::FourOfAKind
         );
         assert_eq!(
-            Hand([Card::K, Card::T, Card::J, Card::J, Card::T,]).classify_q2(),
+            Hand(vec![Card::K, Card::T, Card::J, Card::J, Card::T,]).classify_q2(),
             HandType::FourOfAKind
         );
     }
+
+    const ALL_CARDS: [Card; 13] = [
+        Card::A,
+        Card::K,
+        Card::Q,
+        Card::J,
+        Card::T,
+        Card::Number(9),
+        Card::Number(8),
+        Card::Number(7),
+        Card::Number(6),
+        Card::Number(5),
+        Card::Number(4),
+        Card::Number(3),
+        Card::Number(2),
+    ];
+
+    /// Every hand of `size` cards drawn from `cards`. Order doesn't affect a hand's type,
+    /// so hands which are rearrangements of each other are only returned once.
+    fn all_hands(cards: &[Card], size: usize) -> Vec<Vec<Card>> {
+        if size == 0 {
+            return vec![Vec::new()];
+        }
+        (0..cards.len())
+            .flat_map(|i| {
+                all_hands(&cards[i..], size - 1)
+                    .into_iter()
+                    .map(move |mut hand| {
+                        hand.push(cards[i]);
+                        hand
+                    })
+            })
+            .collect()
+    }
+
+    /// Frequencies from least to most frequent, without any wildcards.
+    fn frequencies(hand: &[Card]) -> Vec<usize> {
+        Hand(hand.to_vec()).card_frequencies(&[])
+    }
+
+    fn card_index(card: Card) -> usize {
+        ALL_CARDS.iter().position(|c| *c == card).unwrap()
+    }
+
+    /// Tries turning the wildcards into every combination of `replacements`, and returns the best frequencies.
+    fn brute_force_frequencies(
+        hand: &[Card],
+        wildcards: &[Card],
+        replacements: &[Card],
+    ) -> Vec<usize> {
+        let mut counts = [0; ALL_CARDS.len()];
+        let mut wild = 0;
+        for card in hand {
+            if wildcards.contains(card) {
+                wild += 1;
+            } else {
+                counts[card_index(*card)] += 1;
+            }
+        }
+        let replacements: Vec<_> = replacements.iter().copied().map(card_index).collect();
+        let mut freqs: Vec<_> = best_frequencies(counts, wild, &replacements)
+            .into_iter()
+            .filter(|n| *n > 0)
+            .collect();
+        freqs.reverse();
+        freqs
+    }
+
+    /// Frequencies from most to least frequent, padded with zeroes.
+    fn best_frequencies(
+        mut counts: [usize; ALL_CARDS.len()],
+        wild: usize,
+        replacements: &[usize],
+    ) -> [usize; ALL_CARDS.len()] {
+        if wild == 0 {
+            counts.sort_unstable_by(|a, b| b.cmp(a));
+            return counts;
+        }
+        // Wildcards are interchangeable, so each later wildcard only needs to try
+        // the same replacement as this one, or a later one.
+        replacements
+            .iter()
+            .enumerate()
+            .map(|(i, replacement)| {
+                let mut counts = counts;
+                counts[*replacement] += 1;
+                best_frequencies(counts, wild - 1, &replacements[i..])
+            })
+            .max()
+            .unwrap()
+    }
+
+    #[test]
+    fn test_jokers_match_brute_force() {
+        let replacements: Vec<_> = ALL_CARDS.into_iter().filter(|c| *c != Card::J).collect();
+        for hand in all_hands(&ALL_CARDS, 5) {
+            let expected = HandType::from_card_frequencies(brute_force_frequencies(
+                &hand,
+                &[Card::J],
+                &replacements,
+            ));
+            assert_eq!(Hand(hand.clone()).classify_q2(), expected, "{hand:?}");
+        }
+    }
+
+    #[test]
+    fn test_several_wildcards_in_bigger_hands() {
+        let wildcards = [Card::J, Card::T];
+        let replacements: Vec<_> = ALL_CARDS
+            .into_iter()
+            .filter(|c| !wildcards.contains(c))
+            .collect();
+        let cards = [Card::A, Card::K, Card::J, Card::T, Card::Number(2)];
+        for hand in all_hands(&cards, 7) {
+            let expected = brute_force_frequencies(&hand, &wildcards, &replacements);
+            assert_eq!(
+                Hand(hand.clone()).card_frequencies(&wildcards),
+                expected,
+                "{hand:?}"
+            );
+        }
+        assert_eq!(
+            HandType::from_card_frequencies(frequencies(&[Card::A; 7])),
+            HandType::FiveOfAKind
+        );
+    }
 }
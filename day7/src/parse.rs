@@ -16,13 +16,7 @@ impl Card {
 impl Row {
     fn parse(s: &str) -> Self {
         let (hand, bid) = s.split_once(' ').unwrap();
-        let hand = Hand(
-            hand.chars()
-                .map(Card::parse)
-                .collect::<Vec<_>>()
-                .try_into()
-                .unwrap(),
-        );
+        let hand = Hand(hand.chars().map(Card::parse).collect());
         let bid = bid.parse().unwrap();
         Row { hand, bid }
     }
@@ -0,0 +1,82 @@
+use super::{ghost_starts, Data, Direction, GhostCycle, Node};
+use std::collections::HashMap;
+use std::fmt::Write;
+
+/// Each ghost's loop gets its own colour. If there are more ghosts than colours, they're reused.
+const LOOP_COLOURS: [&str; 6] = ["blue", "darkorange", "purple", "darkgreen", "red", "brown"];
+
+impl Data {
+    /// Renders the network in Graphviz's DOT format.
+    /// Start nodes (ending in A) are filled green and end nodes (ending in Z) are filled red.
+    /// The loop each ghost ends up stuck in is outlined in that ghost's colour.
+    pub fn to_dot(&self) -> String {
+        let mut node_colours: HashMap<Node, &str> = HashMap::new();
+        let mut edge_colours: HashMap<(Node, Direction), &str> = HashMap::new();
+        for (i, start) in ghost_starts(self).enumerate() {
+            let colour = LOOP_COLOURS[i % LOOP_COLOURS.len()];
+            let cycle = GhostCycle::analyse(self, start);
+            for (node, dir) in self.walk(start).skip(cycle.tail).take(cycle.period) {
+                node_colours.entry(node).or_insert(colour);
+                edge_colours.entry((node, dir)).or_insert(colour);
+            }
+        }
+
+        let mut out = String::from("digraph desert {\n");
+        for node in self.all_nodes() {
+            let name = self.name(node);
+            let mut attrs = Vec::new();
+            if name.ends_with('A') {
+                attrs.push("style=filled, fillcolor=palegreen".to_owned());
+            } else if name.ends_with('Z') {
+                attrs.push("style=filled, fillcolor=lightcoral".to_owned());
+            }
+            if let Some(colour) = node_colours.get(&node) {
+                attrs.push(format!("color={colour}, penwidth=2"));
+            }
+            if attrs.is_empty() {
+                writeln!(out, "    \"{name}\";").unwrap();
+            } else {
+                writeln!(out, "    \"{name}\" [{}];", attrs.join(", ")).unwrap();
+            }
+        }
+        for node in self.all_nodes() {
+            for (dir, label) in [(Direction::Left, "L"), (Direction::Right, "R")] {
+                let (from, to) = (self.name(node), self.name(self.step(node, dir)));
+                write!(out, "    \"{from}\" -> \"{to}\" [label={label}").unwrap();
+                if let Some(colour) = edge_colours.get(&(node, dir)) {
+                    write!(out, ", color={colour}, penwidth=2").unwrap();
+                }
+                out.push_str("];\n");
+            }
+        }
+        out.push_str("}\n");
+        out
+    }
+
+    /// Every (node, direction taken from it) along the walk starting at `start`. Never ends.
+    fn walk(&self, start: Node) -> impl Iterator<Item = (Node, Direction)> + '_ {
+        let mut curr = start;
+        self.directions.iter().cycle().map(move |dir| {
+            let node = curr;
+            curr = self.step(curr, *dir);
+            (node, *dir)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_example_snapshot() {
+        let data = Data::parse(include_str!("../example.txt")).unwrap();
+        assert_eq!(data.to_dot(), include_str!("../example.dot"));
+    }
+
+    #[test]
+    fn test_example2_snapshot() {
+        let data = Data::parse(include_str!("../example2.txt")).unwrap();
+        assert_eq!(data.to_dot(), include_str!("../example2.dot"));
+    }
+}
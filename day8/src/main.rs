@@ -1,13 +1,53 @@
 use std::collections::HashMap;
 
-fn main() {
+mod dot;
+
+fn main() -> Result<(), MapError> {
     let input = include_str!("../input.txt");
-    let data = Data::parse(input);
-    println!("Q1: {}", q1(&data));
-    println!("Q2: {}", q2(&data));
+    let data = Data::parse(input)?;
+    if std::env::args().any(|arg| arg == "--dot") {
+        // e.g. `cargo run -- --dot | dot -Tsvg > network.svg`
+        print!("{}", data.to_dot());
+        return Ok(());
+    }
+    println!("Q1: {}", q1(&data)?);
+    println!("Q2: {}", q2(&data)?);
+    Ok(())
 }
 
-#[derive(Debug)]
+/// Why a walk through the desert can't be completed.
+#[derive(Debug, PartialEq, Eq)]
+enum MapError {
+    /// There are no directions to follow.
+    NoDirections,
+    /// This node is referenced but never defined.
+    MissingNode(String),
+    /// Following the directions from `from` loops forever without reaching `to`.
+    Unreachable { from: String, to: String },
+    /// There are no nodes ending in A for ghosts to start from.
+    NoGhosts,
+    /// The ghosts loop forever without all being on Z nodes at once.
+    GhostsNeverSync,
+    /// The ghosts only sync up after more steps than fit in a usize.
+    TooManySteps,
+}
+
+impl std::fmt::Display for MapError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::NoDirections => write!(f, "there are no directions to follow"),
+            Self::MissingNode(name) => write!(f, "node {name} is never defined"),
+            Self::Unreachable { from, to } => {
+                write!(f, "following the directions from {from} never reaches {to}")
+            }
+            Self::NoGhosts => write!(f, "no nodes end in A"),
+            Self::GhostsNeverSync => write!(f, "the ghosts are never all on Z nodes at once"),
+            Self::TooManySteps => write!(f, "the ghosts take too many steps to count"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 enum Direction {
     Right,
     Left,
@@ -25,80 +65,263 @@ impl From<char> for Direction {
     }
 }
 
-type Node = String;
+/// Identifies a node. IDs are dense, i.e. they go from 0 up to the number of nodes.
+type Node = u32;
 
 /// A map of the desert, given as problem input.
 #[derive(Debug)]
 struct Data {
     directions: Vec<Direction>,
-    nodes: HashMap<Node, (Node, Node)>,
+    /// Each node's (left, right) neighbours, indexed by node.
+    nodes: Vec<[Node; 2]>,
+    /// Each node's name, indexed by node.
+    names: Vec<String>,
+    /// Looks up a node by its name.
+    ids: HashMap<String, Node>,
 }
 
 impl Data {
-    fn parse(s: &str) -> Self {
+    /// Fails if any node is referenced without being defined, or there are no directions.
+    fn parse(s: &str) -> Result<Self, MapError> {
         let mut lines = s.lines();
         let directions: Vec<_> = lines.next().unwrap().chars().map(Direction::from).collect();
+        if directions.is_empty() {
+            return Err(MapError::NoDirections);
+        }
         lines.next().unwrap();
-        let nodes: HashMap<_, _> = lines
+        let edges: Vec<_> = lines
             .map(|line| {
                 let (key, val) = line.split_once(" = ").unwrap();
                 let val_without_parens = &val[1..val.len() - 1];
                 let (l, r) = val_without_parens.split_once(", ").unwrap();
-                (key.to_owned(), (l.to_owned(), r.to_owned()))
+                (key, [l, r])
             })
             .collect();
-        Self { directions, nodes }
+        let names: Vec<_> = edges.iter().map(|(key, _)| key.to_string()).collect();
+        let ids: HashMap<_, _> = names
+            .iter()
+            .enumerate()
+            .map(|(id, name)| (name.to_owned(), id as Node))
+            .collect();
+        let mut nodes = Vec::with_capacity(edges.len());
+        for (_, [l, r]) in edges {
+            let lookup = |name: &str| {
+                ids.get(name)
+                    .copied()
+                    .ok_or_else(|| MapError::MissingNode(name.to_owned()))
+            };
+            nodes.push([lookup(l)?, lookup(r)?]);
+        }
+        Ok(Self {
+            directions,
+            nodes,
+            names,
+            ids,
+        })
+    }
+
+    fn id(&self, name: &str) -> Result<Node, MapError> {
+        self.ids
+            .get(name)
+            .copied()
+            .ok_or_else(|| MapError::MissingNode(name.to_owned()))
+    }
+
+    fn name(&self, node: Node) -> &str {
+        &self.names[node as usize]
+    }
+
+    /// Where you end up if you leave `node` going in that direction.
+    fn step(&self, node: Node, dir: Direction) -> Node {
+        let [to_left, to_right] = self.nodes[node as usize];
+        match dir {
+            Direction::Right => to_right,
+            Direction::Left => to_left,
+        }
+    }
+
+    /// All nodes, in ID order.
+    fn all_nodes(&self) -> impl Iterator<Item = Node> {
+        0..self.nodes.len() as Node
     }
 }
 
-fn q1(data: &Data) -> usize {
-    let mut curr = "AAA".to_owned();
-    for (dir, steps) in data.directions.iter().cycle().zip(0..) {
-        if curr == "ZZZ" {
-            return steps;
+fn q1(data: &Data) -> Result<usize, MapError> {
+    let start = data.id("AAA")?;
+    let end = data.id("ZZZ")?;
+    // The walk is fully determined by the (node, direction index) state. If the walk is back
+    // on a node at the start of the directions before reaching the end, it's stuck in a loop
+    // which never reaches it.
+    let n = data.directions.len();
+    let mut seen = vec![false; data.nodes.len()];
+    let mut curr = start;
+    for steps in 0.. {
+        if curr == end {
+            return Ok(steps);
+        }
+        if steps % n == 0 && std::mem::replace(&mut seen[curr as usize], true) {
+            break;
         }
-        let (to_left, to_right) = data.nodes.get(&curr).unwrap();
-        curr = match dir {
-            Direction::Right => to_right.to_owned(),
-            Direction::Left => to_left.to_owned(),
-        };
-    }
-    unreachable!()
-}
-
-fn q2(data: &Data) -> usize {
-    let starting_nodes = data
-        .nodes
-        .keys()
-        .filter(|node| node.ends_with('A'))
-        .cloned();
-    let cycle_lengths = starting_nodes.map(|mut curr| {
-        for (dir, steps) in data.directions.iter().cycle().zip(0usize..) {
-            if curr.ends_with('Z') {
-                return steps;
+        curr = data.step(curr, data.directions[steps % n]);
+    }
+    Err(MapError::Unreachable {
+        from: "AAA".to_owned(),
+        to: "ZZZ".to_owned(),
+    })
+}
+
+/// Every node a ghost starts from.
+fn ghost_starts(data: &Data) -> impl Iterator<Item = Node> + '_ {
+    data.all_nodes()
+        .filter(|node| data.name(*node).ends_with('A'))
+}
+
+fn q2(data: &Data) -> Result<usize, MapError> {
+    let cycles: Vec<_> = ghost_starts(data)
+        .map(|start| GhostCycle::analyse(data, start))
+        .collect();
+    if cycles.is_empty() {
+        return Err(MapError::NoGhosts);
+    }
+    earliest_common_step(&cycles)?.ok_or(MapError::GhostsNeverSync)
+}
+
+/// Where a ghost's walk ends up repeating.
+/// A ghost's state is its node and its position in the directions. There are finitely many
+/// states, so eventually the ghost must revisit one, and from then on it loops forever.
+#[derive(Debug, PartialEq, Eq)]
+struct GhostCycle {
+    /// Steps taken before the ghost is known to be in its loop.
+    tail: usize,
+    /// How many steps it takes to go around the loop (or a multiple of that).
+    period: usize,
+    /// Steps (before entering the loop) when the ghost is on a Z node.
+    tail_hits: Vec<usize>,
+    /// Steps (during the first trip around the loop) when the ghost is on a Z node.
+    /// The ghost will be on a Z node again every `period` steps after each of these.
+    cycle_hits: Vec<usize>,
+}
+
+impl GhostCycle {
+    fn analyse(data: &Data, start: Node) -> Self {
+        let n = data.directions.len();
+        // The walk repeats once the ghost is back on a node at the start of the directions,
+        // so only those states need remembering: when each node was first seen there.
+        // This can find the loop up to `n` steps late, which is still a point in the loop.
+        let mut first_seen: Vec<Option<usize>> = vec![None; data.nodes.len()];
+        let mut hits = Vec::new();
+        let mut curr = start;
+        for step in 0.. {
+            if step % n == 0 {
+                if let Some(tail) = first_seen[curr as usize] {
+                    let (tail_hits, cycle_hits) = hits.into_iter().partition(|hit| *hit < tail);
+                    return Self {
+                        tail,
+                        period: step - tail,
+                        tail_hits,
+                        cycle_hits,
+                    };
+                }
+                first_seen[curr as usize] = Some(step);
             }
-            let (to_left, to_right) = data.nodes.get(&curr).unwrap();
-            curr = match dir {
-                Direction::Right => to_right.to_owned(),
-                Direction::Left => to_left.to_owned(),
-            };
+            if data.name(curr).ends_with('Z') {
+                hits.push(step);
+            }
+            curr = data.step(curr, data.directions[step % n]);
         }
         unreachable!()
-    });
-    lowest_common_multiple(cycle_lengths)
+    }
+
+    /// Is the ghost on a Z node after this many steps?
+    fn is_hit(&self, step: usize) -> bool {
+        if step < self.tail {
+            return self.tail_hits.contains(&step);
+        }
+        let offset = self.tail + (step - self.tail) % self.period;
+        self.cycle_hits.contains(&offset)
+    }
 }
 
-fn lowest_common_multiple(numbers: impl Iterator<Item = usize>) -> usize {
-    fn lcm(a: usize, b: usize) -> usize {
-        a * b / gcd(a, b)
+/// The first step at which every ghost is on a Z node, or None if that never happens.
+fn earliest_common_step(cycles: &[GhostCycle]) -> Result<Option<usize>, MapError> {
+    // Maybe they all line up before some ghost has entered its loop.
+    let from_tails = cycles
+        .iter()
+        .flat_map(|cycle| cycle.tail_hits.iter().copied())
+        .filter(|step| cycles.iter().all(|cycle| cycle.is_hit(*step)))
+        .min();
+    // Otherwise they have to line up when every ghost is looping. Each ghost can be at any
+    // of its loop's Z nodes, so try each combination and solve the congruences.
+    let Some(longest_tail) = cycles.iter().map(|cycle| cycle.tail).max() else {
+        return Ok(None);
+    };
+    let mut candidates = vec![(0, 1)];
+    for cycle in cycles {
+        candidates = candidates
+            .into_iter()
+            .flat_map(|candidate| {
+                cycle.cycle_hits.iter().filter_map(move |hit| {
+                    chinese_remainder(candidate, (*hit, cycle.period)).transpose()
+                })
+            })
+            .collect::<Result<_, _>>()?;
     }
-    fn gcd(mut a: usize, mut b: usize) -> usize {
-        while b != 0 {
-            (a, b) = (b, a % b)
-        }
-        a
+    let from_cycles = candidates
+        .into_iter()
+        .map(|(step, modulus)| {
+            if step >= longest_tail {
+                Ok(step)
+            } else {
+                (longest_tail - step)
+                    .div_ceil(modulus)
+                    .checked_mul(modulus)
+                    .and_then(|skipped| skipped.checked_add(step))
+                    .ok_or(MapError::TooManySteps)
+            }
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(from_tails.into_iter().chain(from_cycles).min())
+}
+
+/// Finds x such that x = a1 (mod m1) and x = a2 (mod m2), even if the moduli aren't coprime.
+/// Returns the smallest such x and the modulus (lcm of m1 and m2) it repeats with,
+/// or None if there's no solution. Fails if the lcm doesn't fit in a usize.
+fn chinese_remainder(
+    (a1, m1): (usize, usize),
+    (a2, m2): (usize, usize),
+) -> Result<Option<(usize, usize)>, MapError> {
+    let g = gcd(m1, m2);
+    let diff = a2 as i128 - a1 as i128;
+    if diff % g as i128 != 0 {
+        return Ok(None);
+    }
+    let lcm = (m1 / g).checked_mul(m2).ok_or(MapError::TooManySteps)?;
+    // Solve a1 + m1 * k = a2 (mod m2) for k. Both factors are reduced mod m2 / g first,
+    // so their product fits in an i128.
+    let (m1_g, m2_g) = ((m1 / g) as i128, (m2 / g) as i128);
+    let k = (diff / g as i128).rem_euclid(m2_g) * mod_inverse(m1_g, m2_g) % m2_g;
+    let x = (a1 as i128 + m1 as i128 * k).rem_euclid(lcm as i128);
+    Ok(Some((x as usize, lcm)))
+}
+
+/// The inverse of `a` modulo `m`. Assumes they're coprime.
+fn mod_inverse(a: i128, m: i128) -> i128 {
+    // Extended Euclidean algorithm.
+    let (mut old_r, mut r) = (a.rem_euclid(m), m);
+    let (mut old_s, mut s) = (1, 0);
+    while r != 0 {
+        let q = old_r / r;
+        (old_r, r) = (r, old_r - q * r);
+        (old_s, s) = (s, old_s - q * s);
+    }
+    old_s.rem_euclid(m)
+}
+
+fn gcd(mut a: usize, mut b: usize) -> usize {
+    while b != 0 {
+        (a, b) = (b, a % b)
     }
-    numbers.reduce(lcm).unwrap()
+    a
 }
 
 #[cfg(test)]
@@ -108,18 +331,137 @@ mod tests {
     #[test]
     fn test_q1() {
         let input = include_str!("../example.txt");
-        let data = Data::parse(input);
+        let data = Data::parse(input).unwrap();
         let actual = q1(&data);
-        let expected = 2;
+        let expected = Ok(2);
         assert_eq!(actual, expected);
     }
 
     #[test]
     fn test_q2() {
         let input = include_str!("../example2.txt");
-        let data = Data::parse(input);
+        let data = Data::parse(input).unwrap();
         let actual = q2(&data);
-        let expected = 6;
+        let expected = Ok(6);
         assert_eq!(actual, expected);
     }
+
+    #[test]
+    fn test_ghost_cycle() {
+        let data = Data::parse(include_str!("../example2.txt")).unwrap();
+        assert_eq!(
+            GhostCycle::analyse(&data, data.id("11A").unwrap()),
+            GhostCycle {
+                tail: 2,
+                period: 2,
+                tail_hits: vec![],
+                cycle_hits: vec![2],
+            }
+        );
+    }
+
+    #[test]
+    fn test_q2_several_z_nodes() {
+        // 11A's ghost is on a Z node at steps 1, 2, 5, 6, 9, 10...
+        // 22A's ghost is on a Z node at steps 3, 6, 9...
+        // so their first Z nodes don't give the answer.
+        let input = "L
+
+11A = (11Z, 11Z)
+11Z = (12Z, 12Z)
+12Z = (11B, 11B)
+11B = (11C, 11C)
+11C = (11Z, 11Z)
+22A = (22B, 22B)
+22B = (22C, 22C)
+22C = (22Z, 22Z)
+22Z = (22B, 22B)";
+        assert_eq!(q2(&Data::parse(input).unwrap()), Ok(6));
+    }
+
+    #[test]
+    fn test_q2_tail_only() {
+        // 33A's ghost is only on a Z node once, before getting stuck on 33B.
+        let input = "L
+
+11A = (11Z, 11Z)
+11Z = (11A, 11A)
+33A = (33Z, 33Z)
+33Z = (33B, 33B)
+33B = (33B, 33B)";
+        assert_eq!(q2(&Data::parse(input).unwrap()), Ok(1));
+    }
+
+    #[test]
+    fn test_q2_never_syncs() {
+        // One ghost is on a Z node at odd steps, the other at even steps.
+        let input = "L
+
+11A = (11Z, 11Z)
+11Z = (11A, 11A)
+22A = (22B, 22B)
+22B = (22Z, 22Z)
+22Z = (22B, 22B)";
+        assert_eq!(
+            q2(&Data::parse(input).unwrap()),
+            Err(MapError::GhostsNeverSync)
+        );
+    }
+
+    #[test]
+    fn test_q1_unreachable() {
+        // ZZZ exists, but AAA only ever visits BBB and CCC.
+        let input = "LR
+
+AAA = (BBB, CCC)
+BBB = (AAA, CCC)
+CCC = (CCC, AAA)
+ZZZ = (ZZZ, ZZZ)";
+        assert_eq!(
+            q1(&Data::parse(input).unwrap()),
+            Err(MapError::Unreachable {
+                from: "AAA".to_owned(),
+                to: "ZZZ".to_owned()
+            })
+        );
+        let data = Data::parse(include_str!("../example2.txt")).unwrap();
+        assert_eq!(q1(&data), Err(MapError::MissingNode("AAA".to_owned())));
+    }
+
+    #[test]
+    fn test_invalid_maps() {
+        assert_eq!(
+            Data::parse("L\n\nAAA = (BBB, ZZZ)\nZZZ = (ZZZ, ZZZ)").unwrap_err(),
+            MapError::MissingNode("BBB".to_owned())
+        );
+        assert_eq!(
+            Data::parse("\n\nAAA = (AAA, AAA)").unwrap_err(),
+            MapError::NoDirections
+        );
+        let data = Data::parse("L\n\nBBB = (BBB, BBB)").unwrap();
+        assert_eq!(q2(&data), Err(MapError::NoGhosts));
+    }
+
+    #[test]
+    fn test_chinese_remainder() {
+        assert_eq!(chinese_remainder((2, 3), (3, 5)), Ok(Some((8, 15))));
+        assert_eq!(chinese_remainder((1, 4), (3, 6)), Ok(Some((9, 12))));
+        assert_eq!(chinese_remainder((1, 4), (2, 6)), Ok(None));
+        // Steps in a ghost's loop can be bigger than its period.
+        assert_eq!(chinese_remainder((0, 1), (7, 5)), Ok(Some((2, 5))));
+    }
+
+    #[test]
+    fn test_chinese_remainder_large() {
+        let (m1, m2) = (u32::MAX as usize - 4, u32::MAX as usize);
+        let (a1, a2) = (m1 - 1, 3 * m2 + 2);
+        let (x, lcm) = chinese_remainder((a1, m1), (a2, m2)).unwrap().unwrap();
+        assert_eq!(lcm, m1 * m2);
+        assert_eq!(x % m1, a1 % m1);
+        assert_eq!(x % m2, a2 % m2);
+        assert_eq!(
+            chinese_remainder((0, usize::MAX), (1, usize::MAX - 1)),
+            Err(MapError::TooManySteps)
+        );
+    }
 }
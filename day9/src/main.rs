@@ -1,51 +1,351 @@
 #![feature(array_windows)]
+use num_bigint::BigInt;
+use num_integer::Integer;
+use num_rational::Ratio;
+use num_traits::{CheckedAdd, CheckedDiv, CheckedMul, CheckedSub, FromPrimitive, Zero};
+use std::{fmt, str::FromStr};
 
 fn main() {
     let input = include_str!("../input.txt");
-    let data = parse(input);
-    let deltas: Vec<_> = data.into_iter().map(all_deltas).collect();
-    let q1: i32 = deltas.iter().map(|h| extrapolate_next_step(h)).sum();
+    let (q1, q2) = match solve::<i64>(input) {
+        Ok((q1, q2)) => (BigInt::from(q1), BigInt::from(q2)),
+        Err(err) if err.problem == Problem::Overflow => {
+            println!("{err}, retrying with big integers");
+            solve::<BigInt>(input).unwrap()
+        }
+        Err(err) => panic!("{err}"),
+    };
+    if let Some(steepest) = steepest_history::<BigInt>(input) {
+        println!("Highest-degree history: {steepest}");
+    }
     println!("Q1: {q1}");
-    let q2: i32 = deltas.iter().map(|h| extrapolate_prev_step(h)).sum();
-    assert_eq!(q2, 1089);
+    assert_eq!(q2, BigInt::from(1089));
     println!("Q2: {q2}");
 }
 
-type Value = i32;
-type History = Vec<Value>;
+/// Solves both questions, storing values as `T`.
+fn solve<T: Value>(input: &str) -> Result<(T, T), HistoryError> {
+    let data: Vec<Readings<T>> = parse(input);
+    let mut q1 = T::zero();
+    let mut q2 = T::zero();
+    for (line, readings) in data.into_iter().enumerate() {
+        let h = fill_gaps(readings).map_err(|problem| HistoryError { line, problem })?;
+        let overflow = HistoryError {
+            line,
+            problem: Problem::Overflow,
+        };
+        let deltas = all_deltas(h).ok_or(overflow)?;
+        let next = extrapolate_next_step(&deltas).ok_or(overflow)?;
+        let prev = extrapolate_prev_step(&deltas).ok_or(overflow)?;
+        q1 = q1.checked_add(&next).ok_or(overflow)?;
+        q2 = q2.checked_add(&prev).ok_or(overflow)?;
+
+        // Fitting a polynomial to the history should give the same answers.
+        // Evaluating the polynomial needs bigger intermediate values than the difference table,
+        // so it can overflow even when the extrapolations fit.
+        let polynomial = Polynomial::fit(&deltas);
+        assert_eq!(polynomial.next(1).ok_or(overflow)?, next);
+        assert_eq!(polynomial.prev(1).ok_or(overflow)?, prev);
+    }
+    Ok((q1, q2))
+}
+
+/// The polynomial with the highest degree, out of those fitted to each history.
+/// Histories which can't be solved are skipped.
+fn steepest_history<T: Value>(input: &str) -> Option<Polynomial<T>> {
+    parse::<T>(input)
+        .into_iter()
+        .filter_map(|readings| fill_gaps(readings).ok())
+        .filter_map(all_deltas)
+        .map(|deltas| Polynomial::fit(&deltas))
+        .reduce(|steepest, p| {
+            if p.degree() > steepest.degree() {
+                p
+            } else {
+                steepest
+            }
+        })
+}
+
+/// An integer type that OASIS values can be stored in, e.g. i64, i128 or BigInt.
+/// All arithmetic on values is checked, so overflow gets reported instead of silently wrapping.
+trait Value:
+    Integer
+    + Clone
+    + CheckedAdd
+    + CheckedSub
+    + CheckedMul
+    + CheckedDiv
+    + FromPrimitive
+    + FromStr<Err: fmt::Debug>
+    + fmt::Display
+    + fmt::Debug
+{
+}
+
+impl<T> Value for T where
+    T: Integer
+        + Clone
+        + CheckedAdd
+        + CheckedSub
+        + CheckedMul
+        + CheckedDiv
+        + FromPrimitive
+        + FromStr<Err: fmt::Debug>
+        + fmt::Display
+        + fmt::Debug
+{
+}
 
-fn all_deltas(h: History) -> Vec<History> {
+type History<T> = Vec<T>;
+/// A history where some readings might be missing (None).
+type Readings<T> = Vec<Option<T>>;
+
+/// A history couldn't be extrapolated.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct HistoryError {
+    /// Which line of the input the history was on, starting from 0.
+    line: usize,
+    problem: Problem,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Problem {
+    /// Some arithmetic overflowed the value type.
+    Overflow,
+    /// Every reading is missing.
+    NoReadings,
+    /// The reading at this index is missing, but it's the first or last one.
+    /// Only gaps between two known readings can be filled.
+    GapAtEdge { index: usize },
+    /// The lowest-degree polynomial through the known readings isn't a whole number
+    /// at this (missing) index, so the known readings don't determine it.
+    Undetermined { index: usize },
+}
+
+impl fmt::Display for HistoryError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "the history on line {} ", self.line + 1)?;
+        match self.problem {
+            Problem::Overflow => write!(f, "overflowed"),
+            Problem::NoReadings => write!(f, "has no readings"),
+            Problem::GapAtEdge { index } => {
+                write!(
+                    f,
+                    "is missing reading {index}, which isn't between two others"
+                )
+            }
+            Problem::Undetermined { index } => {
+                write!(f, "doesn't determine missing reading {index}")
+            }
+        }
+    }
+}
+
+/// Fills in missing readings using the unique lowest-degree polynomial through the known ones.
+fn fill_gaps<T: Value>(readings: Readings<T>) -> Result<History<T>, Problem> {
+    let known: Vec<(T, T)> = readings
+        .iter()
+        .enumerate()
+        .filter_map(|(i, reading)| Some((T::from_usize(i)?, reading.clone()?)))
+        .collect();
+    if known.is_empty() {
+        return Err(Problem::NoReadings);
+    }
+    if known.len() == readings.len() {
+        return Ok(readings.into_iter().flatten().collect());
+    }
+    for index in [0, readings.len() - 1] {
+        if readings[index].is_none() {
+            return Err(Problem::GapAtEdge { index });
+        }
+    }
+
+    // Newton's divided differences. The polynomial through n points, with degree at most n-1, is unique,
+    // and any lower-degree polynomial through those points would be the same polynomial.
+    let xs: Vec<T> = known.iter().map(|(x, _)| x.clone()).collect();
+    let mut coefficients: Vec<Ratio<T>> = known.into_iter().map(|(_, y)| Ratio::from(y)).collect();
+    for j in 1..xs.len() {
+        for i in (j..xs.len()).rev() {
+            let dy = coefficients[i].checked_sub(&coefficients[i - 1]);
+            let dx = xs[i].checked_sub(&xs[i - j]).map(Ratio::from);
+            coefficients[i] = dy
+                .zip(dx)
+                .and_then(|(dy, dx)| dy.checked_div(&dx))
+                .ok_or(Problem::Overflow)?;
+        }
+    }
+    let interpolate = |x: &T| -> Option<Ratio<T>> {
+        let (last, rest) = coefficients.split_last()?;
+        rest.iter()
+            .zip(&xs)
+            .rev()
+            .try_fold(last.clone(), |total, (coefficient, xi)| {
+                let factor = Ratio::from(x.checked_sub(xi)?);
+                total.checked_mul(&factor)?.checked_add(coefficient)
+            })
+    };
+
+    let mut history = Vec::with_capacity(readings.len());
+    for (index, reading) in readings.into_iter().enumerate() {
+        let value = match reading {
+            Some(value) => value,
+            None => {
+                let x = T::from_usize(index).ok_or(Problem::Overflow)?;
+                let value = interpolate(&x).ok_or(Problem::Overflow)?;
+                if !value.is_integer() {
+                    return Err(Problem::Undetermined { index });
+                }
+                value.to_integer()
+            }
+        };
+        history.push(value);
+    }
+    Ok(history)
+}
+
+/// None if any delta overflows.
+fn all_deltas<T: Value>(h: History<T>) -> Option<Vec<History<T>>> {
     let mut rows = vec![];
     let mut next_row = h;
     let mut all_zeroes = false;
     while !all_zeroes {
         rows.push(next_row);
         let curr_row = rows.last().unwrap();
-        next_row = curr_row.array_windows().map(|[x, y]| y - x).collect();
-        all_zeroes = next_row.iter().all(|&x| x == 0);
+        next_row = curr_row
+            .array_windows()
+            .map(|[x, y]| y.checked_sub(x))
+            .collect::<Option<_>>()?;
+        all_zeroes = next_row.iter().all(T::is_zero);
     }
-    rows
+    Some(rows)
+}
+
+fn extrapolate_next_step<T: Value>(h: &[History<T>]) -> Option<T> {
+    h.iter().rev().try_fold(T::zero(), |next, row| {
+        row.last().unwrap().checked_add(&next)
+    })
 }
 
-fn extrapolate_next_step(h: &[History]) -> Value {
-    h.iter()
-        .rev()
-        .fold(0, |next, row| row.last().unwrap() + next)
+fn extrapolate_prev_step<T: Value>(h: &[History<T>]) -> Option<T> {
+    h.iter().rev().try_fold(T::zero(), |next, row| {
+        row.first().unwrap().checked_sub(&next)
+    })
 }
 
-fn extrapolate_prev_step(h: &[History]) -> Value {
-    h.iter()
-        .rev()
-        .fold(0, |next, row| row.first().unwrap() - next)
+/// The unique lowest-degree polynomial through every value of a history,
+/// as a function of each value's index (so the first value is at 0).
+#[derive(Debug, PartialEq, Eq)]
+struct Polynomial<T> {
+    /// The first value in each row of the difference table. By Newton's forward difference
+    /// formula, p(x) = sum over k of differences[k] * (x choose k).
+    differences: Vec<T>,
+    /// How many values the polynomial was fitted to.
+    len: usize,
 }
 
-fn parse(s: &str) -> Vec<History> {
+impl<T: Value> Polynomial<T> {
+    /// Fits a polynomial to a history's difference table (see `all_deltas`).
+    fn fit(h: &[History<T>]) -> Self {
+        Self {
+            differences: h.iter().map(|row| row[0].clone()).collect(),
+            len: h[0].len(),
+        }
+    }
+
+    fn degree(&self) -> usize {
+        self.differences.len() - 1
+    }
+
+    /// The value at any index, including before the first value (negative indices) or after the last.
+    /// None if the calculation overflows.
+    fn at(&self, x: i64) -> Option<T> {
+        let x = T::from_i64(x)?;
+        let mut total = T::zero();
+        // Generalised binomial coefficient (x choose k), which is an integer even for negative x.
+        let mut choose = T::one();
+        for (k, difference) in self.differences.iter().enumerate() {
+            if k > 0 {
+                // (x choose k) = (x choose k-1) * (x - k + 1) / k, and the division is always exact.
+                let k = T::from_usize(k)?;
+                let factor = x.checked_sub(&k)?.checked_add(&T::one())?;
+                choose = choose.checked_mul(&factor)?.checked_div(&k)?;
+            }
+            total = total.checked_add(&difference.checked_mul(&choose)?)?;
+        }
+        Some(total)
+    }
+
+    /// The value `k` steps after the last value.
+    fn next(&self, k: usize) -> Option<T> {
+        self.at((self.len - 1 + k) as i64)
+    }
+
+    /// The value `k` steps before the first value.
+    fn prev(&self, k: usize) -> Option<T> {
+        self.at(-(k as i64))
+    }
+
+    /// Exact coefficients in the usual basis, starting with the constant term.
+    /// None if the calculation overflows.
+    fn coefficients(&self) -> Option<Vec<Ratio<T>>> {
+        let mut coefficients = vec![Ratio::from_integer(T::zero()); self.differences.len()];
+        // The falling factorial x(x-1)...(x-k+1), starting with k = 0.
+        let mut falling: Vec<T> = vec![T::one()];
+        let mut factorial = T::one();
+        for (k, difference) in self.differences.iter().enumerate() {
+            let k = T::from_usize(k)?;
+            if !k.is_zero() {
+                factorial = factorial.checked_mul(&k)?;
+            }
+            for (power, coefficient) in falling.iter().enumerate() {
+                let term = Ratio::new(difference.checked_mul(coefficient)?, factorial.clone());
+                coefficients[power] = coefficients[power].checked_add(&term)?;
+            }
+            // Multiply by (x - k).
+            let mut next = vec![T::zero(); falling.len() + 1];
+            for (power, coefficient) in falling.iter().enumerate() {
+                next[power + 1] = next[power + 1].checked_add(coefficient)?;
+                next[power] = next[power].checked_sub(&coefficient.checked_mul(&k)?)?;
+            }
+            falling = next;
+        }
+        Some(coefficients)
+    }
+}
+
+impl<T: Value> fmt::Display for Polynomial<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let Some(coefficients) = self.coefficients() else {
+            return write!(f, "(too big to expand)");
+        };
+        let terms: Vec<_> = coefficients
+            .into_iter()
+            .enumerate()
+            .rev()
+            .filter(|(_, coefficient)| !coefficient.is_zero())
+            .map(|(power, coefficient)| match power {
+                0 => format!("{coefficient}"),
+                1 => format!("({coefficient})x"),
+                _ => format!("({coefficient})x^{power}"),
+            })
+            .collect();
+        if terms.is_empty() {
+            write!(f, "0")
+        } else {
+            write!(f, "{}", terms.join(" + "))
+        }
+    }
+}
+
+fn parse<T: Value>(s: &str) -> Vec<Readings<T>> {
     s.lines().map(parse_line).collect()
 }
 
-fn parse_line(line: &str) -> History {
+/// A `?` means the reading is missing.
+fn parse_line<T: Value>(line: &str) -> Readings<T> {
     line.split_ascii_whitespace()
-        .map(|n| n.parse().unwrap())
+        .map(|n| (n != "?").then(|| n.parse().unwrap()))
         .collect()
 }
 
@@ -53,10 +353,18 @@ fn parse_line(line: &str) -> History {
 mod tests {
     use super::*;
 
+    /// Parses a history without any missing readings.
+    fn history<T: Value>(line: &str) -> History<T> {
+        fill_gaps(parse_line(line)).unwrap()
+    }
+
     #[test]
     fn test_name() {
-        let data = parse(include_str!("../example.txt"));
-        let all_ds: Vec<_> = data.into_iter().map(all_deltas).collect();
+        let data: Vec<History<i32>> = include_str!("../example.txt")
+            .lines()
+            .map(history)
+            .collect();
+        let all_ds: Vec<_> = data.into_iter().map(|h| all_deltas(h).unwrap()).collect();
         let expected_all_ds =
             vec![
                 vec![vec![0, 3, 6, 9, 12, 15], vec![3, 3, 3, 3, 3]],
@@ -75,8 +383,129 @@ mod tests {
         assert_eq!(all_ds, expected_all_ds);
         let all_extrapolateds: Vec<_> = all_ds
             .into_iter()
-            .map(|h| extrapolate_next_step(&h))
+            .map(|h| extrapolate_next_step(&h).unwrap())
             .collect();
         assert_eq!(all_extrapolateds, vec![18, 28, 68]);
     }
+
+    #[test]
+    fn test_polynomial() {
+        let data: Vec<History<i32>> = include_str!("../example.txt")
+            .lines()
+            .map(history)
+            .collect();
+        let polynomials: Vec<_> = data
+            .into_iter()
+            .map(|h| Polynomial::fit(&all_deltas(h).unwrap()))
+            .collect();
+        let degrees: Vec<_> = polynomials.iter().map(Polynomial::degree).collect();
+        assert_eq!(degrees, vec![1, 2, 3]);
+        let next: Vec<_> = polynomials.iter().map(|p| p.next(1).unwrap()).collect();
+        assert_eq!(next, vec![18, 28, 68]);
+        let prev: Vec<_> = polynomials.iter().map(|p| p.prev(1).unwrap()).collect();
+        assert_eq!(prev, vec![-3, 0, 5]);
+
+        // 1 3 6 10 15 21 are the triangle numbers, (x+1)(x+2)/2.
+        let triangle = &polynomials[1];
+        assert_eq!(
+            triangle.coefficients(),
+            Some(vec![
+                Ratio::from_integer(1),
+                Ratio::new(3, 2),
+                Ratio::new(1, 2)
+            ])
+        );
+        assert_eq!(triangle.to_string(), "(1/2)x^2 + (3/2)x + 1");
+        assert_eq!(triangle.next(3), Some(45));
+        assert_eq!(triangle.prev(3), Some(1));
+        assert_eq!(triangle.at(99), Some(5050));
+    }
+
+    #[test]
+    fn test_overflow() {
+        // The second history's deltas don't fit in an i64, but do fit in an i128.
+        let input = "1 2 3\n9223372036854775807 -9223372036854775808 9223372036854775807";
+        assert_eq!(
+            solve::<i64>(input),
+            Err(HistoryError {
+                line: 1,
+                problem: Problem::Overflow
+            })
+        );
+        let (q1, q2) = solve::<i128>(input).unwrap();
+        let (max, min) = (i64::MAX as i128, i64::MIN as i128);
+        // The second history is quadratic, with a constant second difference of 4 * max + 2.
+        let second_difference = 4 * max + 2;
+        assert_eq!(q1, 4 + (max + (max - min) + second_difference));
+        assert_eq!(q2, max - (min - max - second_difference));
+        let big = solve::<BigInt>(input).unwrap();
+        assert_eq!(big, (BigInt::from(q1), BigInt::from(q2)));
+    }
+
+    #[test]
+    fn test_steep_history() {
+        // Consecutive readings are too far apart for an i32 to hold the difference.
+        let h: History<i32> = history("-2000000000 2000000000");
+        assert_eq!(all_deltas(h), None);
+        let h: History<i64> = history("-2000000000 2000000000");
+        assert_eq!(
+            extrapolate_next_step(&all_deltas(h).unwrap()),
+            Some(6000000000)
+        );
+
+        // Powers of 10, far too big for an i128.
+        let input: Vec<_> = (0..50).map(|k| format!("1{}", "0".repeat(k))).collect();
+        let h: History<BigInt> = history(&input.join(" "));
+        let deltas = all_deltas(h).unwrap();
+        let polynomial = Polynomial::fit(&deltas);
+        assert_eq!(polynomial.degree(), 49);
+        assert_eq!(polynomial.next(1), extrapolate_next_step(&deltas));
+        assert_eq!(polynomial.prev(1), extrapolate_prev_step(&deltas));
+    }
+
+    #[test]
+    fn test_fill_gaps() {
+        let fill = |line| fill_gaps::<i64>(parse_line(line));
+        assert_eq!(fill("0 3 ? 9 ? 15"), Ok(vec![0, 3, 6, 9, 12, 15]));
+        assert_eq!(
+            fill("10 ? ? ? 30 45 68"),
+            Ok(vec![10, 13, 16, 21, 30, 45, 68])
+        );
+        // Only two readings, so the lowest-degree polynomial through them is a line.
+        assert_eq!(fill("1 ? ? 7"), Ok(vec![1, 3, 5, 7]));
+        assert_eq!(fill("1 ? 4"), Err(Problem::Undetermined { index: 1 }));
+        assert_eq!(fill("? 3 6"), Err(Problem::GapAtEdge { index: 0 }));
+        assert_eq!(fill("3 6 ?"), Err(Problem::GapAtEdge { index: 2 }));
+        assert_eq!(fill("? ?"), Err(Problem::NoReadings));
+    }
+
+    #[test]
+    fn test_polynomial_overflow() {
+        // The differences and extrapolations all fit in an i64, but evaluating the polynomial
+        // doesn't, so this has to be retried with bigger integers.
+        let input = "1152921504606846976 -1152921504606846976 1152921504606846976";
+        assert_eq!(
+            solve::<i64>(input),
+            Err(HistoryError {
+                line: 0,
+                problem: Problem::Overflow
+            })
+        );
+        let big = solve::<BigInt>(input).unwrap();
+        assert_eq!(big.0, BigInt::from(8070450532247928832i64));
+    }
+
+    #[test]
+    fn test_solve_with_gaps() {
+        let input = "0 3 ? 9 12 15\n1 ? 6 10 ? 21\n10 13 ? 21 30 45";
+        assert_eq!(solve::<i64>(input), Ok((114, 2)));
+        let input = "1 2 3\n1 ? 4";
+        assert_eq!(
+            solve::<i64>(input),
+            Err(HistoryError {
+                line: 1,
+                problem: Problem::Undetermined { index: 1 }
+            })
+        );
+    }
 }